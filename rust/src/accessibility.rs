@@ -0,0 +1,88 @@
+use crate::{Decay, PopulationPoint};
+use rayon::prelude::*;
+
+/// The two-step floating catchment area (2SFCA) accessibility of every
+/// population point, in the same order as the points.
+///
+/// within are the IDs and distances of the stations within the catchment
+/// radius of every point, and supply the supply of every station by ID,
+/// such as its weight. First, every station's supply is divided by the
+/// population within its catchment, weighted by the decay. Then every point
+/// sums the ratios of the stations within its catchment, weighted by the
+/// decay again. With the step decay this is the classic 2SFCA, with a
+/// decaying one the enhanced E2SFCA.
+///
+/// The score is the supply per resident: summed over the population of
+/// every point, it adds up to the supply of the stations that have anyone
+/// in their catchment
+pub fn two_step_fca(
+    points: &[PopulationPoint],
+    within: &[Vec<(usize, f64)>],
+    supply: &[f64],
+    decay: Decay,
+) -> Vec<f64> {
+    let mut demand = vec![0.0; supply.len()];
+    for (p, within) in points.iter().zip(within) {
+        for (id, d) in within {
+            demand[*id] += p.pop * decay.weight(*d);
+        }
+    }
+    let ratios: Vec<_> = supply
+        .iter()
+        .zip(demand)
+        .map(|(supply, demand)| {
+            // stations with nobody in their catchment serve nobody
+            if demand > 0.0 {
+                supply / demand
+            } else {
+                0.0
+            }
+        })
+        .collect();
+
+    within
+        .par_iter()
+        .map(|within| {
+            within
+                .iter()
+                .map(|(id, d)| ratios[*id] * decay.weight(*d))
+                .fold(0.0, |a, b| a + b)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point(pop: f64) -> PopulationPoint {
+        PopulationPoint {
+            lon: 0.0,
+            lat: 0.0,
+            pop,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_two_step_fca() {
+        let points = [point(10.0), point(30.0), point(60.0), point(5.0)];
+        let within = [
+            vec![(0, 100.0)],
+            vec![(0, 500.0), (1, 300.0)],
+            vec![(1, 0.0)],
+            vec![],
+        ];
+        let scores = two_step_fca(&points, &within, &[1.0, 2.0], Decay::Step);
+        assert_eq!(scores[0], 1.0 / 40.0);
+        assert!((scores[1] - (1.0 / 40.0 + 2.0 / 90.0)).abs() < 1e-12);
+        assert_eq!(scores[3], 0.0);
+
+        let decay = Decay::Gaussian { sigma: 400.0 };
+        let scores = two_step_fca(&points, &within, &[1.0, 2.0], decay);
+        let total: f64 =
+            points.iter().zip(scores).map(|(p, s)| p.pop * s).sum();
+        assert!((total - 3.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,446 @@
+use crate::{save_point_layer, DistanceModel, PointRow, PopulationPoint};
+use rand::rngs::SmallRng;
+use rand::seq::{index, SliceRandom};
+use rand::SeedableRng;
+use rayon::prelude::*;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use std::str::FromStr;
+
+/// The population points in a tree, with their index as their data
+pub type PointTree = RTree<GeomWithData<(f64, f64), usize>>;
+
+/// Which points are the neighbours of a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Neighbours {
+    /// The k nearest points
+    Nearest(usize),
+    /// Every point within this many meters on the ground
+    Band(f64),
+}
+
+impl FromStr for Neighbours {
+    type Err = String;
+
+    /// knn:K or band:METERS
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expected = || {
+            format!("unknown neighbours `{}`, expected knn:K or band:METERS", s)
+        };
+        let (name, param) = s.split_once(':').ok_or_else(expected)?;
+        match name {
+            "knn" => param
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|k| *k > 0)
+                .map(Self::Nearest)
+                .ok_or_else(|| format!("`{}` is not a positive count", param)),
+            "band" => param
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|d| *d > 0.0)
+                .map(Self::Band)
+                .ok_or_else(|| {
+                    format!("`{}` is not a positive distance", param)
+                }),
+            _ => Err(expected()),
+        }
+    }
+}
+
+/// Row-standardised spatial weights: every point weighs each of its
+/// neighbours equally, adding up to 1
+pub struct SpatialWeights {
+    /// The indices of the neighbours of every point
+    pub neighbours: Vec<Vec<usize>>,
+}
+
+impl SpatialWeights {
+    /// Find the neighbours of every point in a tree of the points, placed as
+    /// the model places them. The k nearest are nearest in the tree, which is
+    /// the same as on the ground but for the small change of the scale factor
+    /// across a city
+    pub fn new(
+        points: &[PopulationPoint],
+        model: DistanceModel,
+        neighbours: Neighbours,
+    ) -> Self {
+        let tree: PointTree = RTree::bulk_load(
+            points
+                .iter()
+                .enumerate()
+                .map(|(i, p)| GeomWithData::new(model.point_position(p), i))
+                .collect(),
+        );
+        let neighbours = points
+            .par_iter()
+            .enumerate()
+            .map(|(i, p)| match neighbours {
+                Neighbours::Nearest(k) => tree
+                    .nearest_neighbor_iter(&model.point_position(p))
+                    .map(|n| n.data)
+                    .filter(|j| *j != i)
+                    .take(k)
+                    .collect(),
+                Neighbours::Band(distance) => {
+                    let mut within: Vec<_> = model
+                        .locate_within_distance(&tree, *p, distance)
+                        .map(|n| n.data)
+                        .filter(|j| *j != i)
+                        .collect();
+                    within.sort_unstable();
+                    within
+                }
+            })
+            .collect();
+        Self { neighbours }
+    }
+
+    /// The number of points without any neighbours, which are left out of
+    /// the statistics
+    pub fn isolates(&self) -> usize {
+        self.neighbours.iter().filter(|n| n.is_empty()).count()
+    }
+
+    /// The mean of the values of the neighbours of every point, its spatial
+    /// lag, or 0 without neighbours
+    pub fn lag(&self, values: &[f64]) -> Vec<f64> {
+        self.neighbours
+            .iter()
+            .map(|n| mean_of(values, n.iter().copied()))
+            .collect()
+    }
+}
+
+fn mean_of(
+    values: &[f64],
+    indices: impl ExactSizeIterator<Item = usize>,
+) -> f64 {
+    let n = indices.len();
+    if n == 0 {
+        return 0.0;
+    }
+    indices.map(|j| values[j]).fold(0.0, |a, b| a + b) / n as f64
+}
+
+/// The deviations of the values from their mean, and their variance
+fn deviations(values: &[f64]) -> Result<(Vec<f64>, f64), String> {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    let z: Vec<_> = values.iter().map(|v| v - mean).collect();
+    let m2 = z.iter().map(|z| z * z).sum::<f64>() / n;
+    // constant values can still deviate by their rounding
+    if m2 <= (1e-12 * mean).powi(2) {
+        return Err("the values need to vary to be autocorrelated".into());
+    }
+    Ok((z, m2))
+}
+
+/// The share of the permutations at least as extreme as the observed
+/// statistic, in the direction it lies from them, counting the observed one
+fn pseudo_p_value(observed: f64, permuted: impl Iterator<Item = f64>) -> f64 {
+    let (mut larger, mut total) = (0, 0);
+    for p in permuted {
+        larger += (p >= observed) as usize;
+        total += 1;
+    }
+    let extreme = larger.min(total - larger);
+    (extreme + 1) as f64 / (total + 1) as f64
+}
+
+/// Global Moran's I, the overall spatial autocorrelation of the values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GlobalMoran {
+    pub i: f64,
+    /// The expected I without autocorrelation, -1 / (n - 1)
+    pub expected: f64,
+    /// From the I of the values randomly permuted between the points
+    pub p_value: f64,
+}
+
+/// Global Moran's I of the values of the points, with a p-value from the
+/// permutations. The same seed gives the same p-value
+pub fn morans_i(
+    values: &[f64],
+    weights: &SpatialWeights,
+    permutations: usize,
+    seed: u64,
+) -> Result<GlobalMoran, String> {
+    let (z, m2) = deviations(values)?;
+    let n = values.len();
+    // with row-standardised weights, every point with neighbours has a row
+    // adding up to 1
+    let s0 = (n - weights.isolates()) as f64;
+    if s0 == 0.0 {
+        return Err("no point has any neighbours".into());
+    }
+    let statistic = |z: &[f64]| {
+        let lag = weights.lag(z);
+        let cross = z.iter().zip(lag).map(|(z, lag)| z * lag).sum::<f64>();
+        cross / (s0 * m2)
+    };
+
+    let i = statistic(&z);
+    let permuted: Vec<_> = (0..permutations as u64)
+        .into_par_iter()
+        .map(|k| {
+            let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(k));
+            let mut z = z.clone();
+            z.shuffle(&mut rng);
+            statistic(&z)
+        })
+        .collect();
+    Ok(GlobalMoran {
+        i,
+        expected: -1.0 / (n as f64 - 1.0),
+        p_value: pseudo_p_value(i, permuted.into_iter()),
+    })
+}
+
+/// The LISA cluster of a point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Cluster {
+    /// A high value among high values
+    HighHigh,
+    /// A low value among low values
+    LowLow,
+    /// A high value among low values
+    HighLow,
+    /// A low value among high values
+    LowHigh,
+    /// Not significantly different from a random arrangement, or without
+    /// neighbours
+    NotSignificant,
+}
+
+impl Cluster {
+    pub const ALL: [Cluster; 5] = [
+        Self::HighHigh,
+        Self::LowLow,
+        Self::HighLow,
+        Self::LowHigh,
+        Self::NotSignificant,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::HighHigh => "high-high",
+            Self::LowLow => "low-low",
+            Self::HighLow => "high-low",
+            Self::LowHigh => "low-high",
+            Self::NotSignificant => "not significant",
+        }
+    }
+}
+
+/// The local Moran's I of a point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Lisa {
+    pub i: f64,
+    /// The spatial lag of the point, the mean value of its neighbours
+    pub lag: f64,
+    /// From the I of the point with the values of random other points as
+    /// its neighbours
+    pub p_value: f64,
+    pub cluster: Cluster,
+}
+
+/// The local Moran's I of every point, in the same order as the values,
+/// with p-values from conditional permutations. Points with a p-value of
+/// at most significance are in a cluster by the sign of their value and
+/// lag relative to the mean. The same seed gives the same p-values
+pub fn lisa(
+    values: &[f64],
+    weights: &SpatialWeights,
+    permutations: usize,
+    significance: f64,
+    seed: u64,
+) -> Result<Vec<Lisa>, String> {
+    let (z, m2) = deviations(values)?;
+    let n = values.len();
+    let lag = weights.lag(values);
+    let mean = values.iter().sum::<f64>() / n as f64;
+
+    let lisas = (0..n)
+        .into_par_iter()
+        .map(|i| {
+            let k = weights.neighbours[i].len();
+            if k == 0 {
+                return Lisa {
+                    i: 0.0,
+                    lag: 0.0,
+                    p_value: 1.0,
+                    cluster: Cluster::NotSignificant,
+                };
+            }
+            let local = |z_lag: f64| z[i] / m2 * z_lag;
+            let observed = local(lag[i] - mean);
+
+            // k of the other points at random, skipping the point itself
+            let mut rng = SmallRng::seed_from_u64(seed.wrapping_add(i as u64));
+            let permuted = (0..permutations).map(|_| {
+                let others = index::sample(&mut rng, n - 1, k)
+                    .into_iter()
+                    .map(|j| if j >= i { j + 1 } else { j });
+                local(mean_of(&z, others))
+            });
+            let p_value = pseudo_p_value(observed, permuted);
+
+            let cluster = match (z[i] > 0.0, lag[i] > mean) {
+                _ if p_value > significance => Cluster::NotSignificant,
+                (true, true) => Cluster::HighHigh,
+                (false, false) => Cluster::LowLow,
+                (true, false) => Cluster::HighLow,
+                (false, true) => Cluster::LowHigh,
+            };
+            Lisa {
+                i: observed,
+                lag: lag[i],
+                p_value,
+                cluster,
+            }
+        })
+        .collect();
+    Ok(lisas)
+}
+
+const COLUMNS: [&str; 8] = [
+    "x", "y", "pop", "value", "lag", "local_i", "p_value", "cluster",
+];
+
+/// A row of the point layer for every population point, in the order of
+/// COLUMNS
+fn lisa_rows(
+    points: &[PopulationPoint],
+    values: &[f64],
+    lisas: &[Lisa],
+) -> Vec<PointRow> {
+    points
+        .iter()
+        .zip(values)
+        .zip(lisas)
+        .map(|((p, value), l)| PointRow {
+            lon: p.lon,
+            lat: p.lat,
+            values: vec![
+                p.x.into(),
+                p.y.into(),
+                p.pop.into(),
+                (*value).into(),
+                l.lag.into(),
+                l.i.into(),
+                l.p_value.into(),
+                l.cluster.name().into(),
+            ],
+        })
+        .collect()
+}
+
+/// Write the local Moran's I of every point as a csv to path, and as
+/// GeoJSON next to it with the .geojson extension. Returns the path of the
+/// GeoJSON
+pub fn save_lisa(
+    path: &str,
+    points: &[PopulationPoint],
+    values: &[f64],
+    lisas: &[Lisa],
+) -> Result<String, Box<dyn std::error::Error>> {
+    save_point_layer(path, &COLUMNS, &lisa_rows(points, values, lisas))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A row of points 100 m apart
+    fn row(n: usize) -> Vec<PopulationPoint> {
+        (0..n)
+            .map(|i| PopulationPoint {
+                lon: 0.0,
+                lat: 0.0,
+                pop: 1.0,
+                x: i as f64 * 100.0,
+                y: 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_neighbours() {
+        assert_eq!("knn:8".parse(), Ok(Neighbours::Nearest(8)));
+        assert_eq!("band:500".parse(), Ok(Neighbours::Band(500.0)));
+        assert!("knn:0".parse::<Neighbours>().is_err());
+        assert!("band".parse::<Neighbours>().is_err());
+        assert!("queen:1".parse::<Neighbours>().is_err());
+    }
+
+    #[test]
+    fn test_weights() {
+        let points = row(5);
+        let knn = SpatialWeights::new(
+            &points,
+            DistanceModel::Planar,
+            Neighbours::Nearest(2),
+        );
+        assert_eq!(knn.neighbours[0], [1, 2]);
+        let mut middle = knn.neighbours[2].clone();
+        middle.sort();
+        assert_eq!(middle, [1, 3]);
+
+        let band = SpatialWeights::new(
+            &points,
+            DistanceModel::Planar,
+            Neighbours::Band(150.0),
+        );
+        assert_eq!(band.neighbours[0], [1]);
+        assert_eq!(band.neighbours[2], [1, 3]);
+        assert_eq!(band.lag(&[0.0, 1.0, 2.0, 3.0, 4.0])[2], 2.0);
+    }
+
+    #[test]
+    fn test_morans_i() {
+        let points = row(40);
+        let weights = SpatialWeights::new(
+            &points,
+            DistanceModel::Planar,
+            Neighbours::Band(150.0),
+        );
+
+        // two halves, low then high, are strongly autocorrelated
+        let halves: Vec<_> = (0..40).map(|i| (i >= 20) as u8 as f64).collect();
+        let global = morans_i(&halves, &weights, 199, 1).unwrap();
+        assert!(global.i > 0.9);
+        assert_eq!(global.p_value, 1.0 / 200.0);
+        assert_eq!(morans_i(&halves, &weights, 199, 1), Ok(global));
+
+        // alternating values are perfectly dispersed
+        let alternating: Vec<_> = (0..40).map(|i| (i % 2) as f64).collect();
+        let global = morans_i(&alternating, &weights, 199, 1).unwrap();
+        assert!((global.i + 1.0).abs() < 0.06);
+
+        assert!(morans_i(&[1.0; 40], &weights, 9, 1).is_err());
+    }
+
+    #[test]
+    fn test_lisa_clusters() {
+        let points = row(40);
+        let weights = SpatialWeights::new(
+            &points,
+            DistanceModel::Planar,
+            Neighbours::Nearest(6),
+        );
+        let mut values: Vec<_> =
+            (0..40).map(|i| (i >= 20) as u8 as f64).collect();
+        // a high outlier among the low half
+        values[15] = 1.0;
+        let lisas = lisa(&values, &weights, 999, 0.05, 7).unwrap();
+        assert_eq!(lisas[2].cluster, Cluster::LowLow);
+        assert_eq!(lisas[15].cluster, Cluster::HighLow);
+        assert_eq!(lisas[35].cluster, Cluster::HighHigh);
+        assert!(lisas[15].i < 0.0);
+        // the neighbours of the boundary are half high, half low
+        assert_eq!(lisas[20].cluster, Cluster::NotSignificant);
+    }
+}
@@ -0,0 +1,194 @@
+use crate::Inputs;
+use plotters::coord::Shift;
+use plotters::{prelude::*, style::full_palette::GREY};
+use rayon::prelude::*;
+use src::{Draw, Metric, Plot, PopulationPoint, Search, StationTree};
+
+pub fn run(
+    inputs: &Inputs,
+    out_filename: String,
+    distances: &[f64],
+) -> Result<(), Box<dyn std::error::Error>> {
+    // the plot has an integer x axis, so fractions would be rounded into
+    // the same boxes
+    if let Some(d) = distances.iter().find(|d| d.fract() != 0.0) {
+        return Err(
+            format!("box takes distances in whole meters, not {}", d).into()
+        );
+    }
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let distances = distances.iter().map(|d| *d as i32).collect();
+    let s = StationWithinPP {
+        out_filename,
+        distances,
+        metric: inputs.metric(network.as_ref()),
+        stations_desc: inputs.stations_desc(),
+    };
+    s.search_to_plot(&tree, &points)
+}
+
+struct StationWithinPP<'a> {
+    out_filename: String,
+    distances: Vec<i32>,
+    metric: Metric<'a>,
+    /// Number of stations, or the sum of their weights
+    stations_desc: String,
+}
+
+impl Search<Vec<f64>> for StationWithinPP<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // this isn't actually used, but here for debugging i suppose
+        eprintln!("searching...");
+
+        let result: Vec<_> = self
+            .distances
+            .iter()
+            .zip(self.sum_all(tree, points))
+            .flat_map(|(max_dist, n_stations)| {
+                n_stations
+                    .into_iter()
+                    .map(move |num| format!("{},{}", max_dist, num))
+            })
+            .collect();
+
+        println!("max_dist,n_stations");
+        for r in result {
+            println!("{}", r);
+        }
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<f64> {
+        self.metric
+            .sum_weights_within_thresholds(tree, points, &[max_distance])
+            .swap_remove(0)
+    }
+}
+
+impl StationWithinPP<'_> {
+    /// The number (or weight) of stations near every point, for every
+    /// distance
+    fn sum_all(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Vec<Vec<f64>> {
+        let distances: Vec<_> =
+            self.distances.iter().map(|d| *d as f64).collect();
+        self.metric
+            .sum_weights_within_thresholds(tree, points, &distances)
+    }
+}
+
+// the result of the search function is Vec<f64> (U),
+// but actual data collected is that result plus the distance threshold,
+// over multiple distances, which is T
+impl Plot<Vec<(i32, Vec<f64>, Vec<f64>)>, Vec<f64>> for StationWithinPP<'_> {
+    fn search_to_plot(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        eprintln!("searching...");
+        let data: Vec<_> = self
+            .distances
+            .par_iter()
+            .copied()
+            .zip(self.sum_all(tree, points))
+            .map(|(max_dist, n_stations)| {
+                let quartiles = Quartiles::new(&n_stations);
+                let lower = quartiles.values()[0];
+                let upper = quartiles.values()[4];
+                let outliers: Vec<_> = n_stations
+                    .iter()
+                    .filter(|x| {
+                        let x = **x as f32;
+                        x < lower || x > upper
+                    })
+                    .cloned()
+                    .collect();
+                (max_dist, n_stations, outliers)
+            })
+            .collect();
+        self.plot(data)
+    }
+}
+
+impl Draw<Vec<(i32, Vec<f64>, Vec<f64>)>> for StationWithinPP<'_> {
+    fn out_file(&self) -> &str {
+        &self.out_filename
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (1500, 768)
+    }
+
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: Vec<(i32, Vec<f64>, Vec<f64>)>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let max_y_value = data
+            .iter()
+            .flat_map(|(_, n_stations, _)| n_stations)
+            .fold(0.0, |a: f64, b| a.max(*b)) as f32;
+
+        let max_x_value =
+            *data.iter().map(|(dist, _, _)| dist).max().unwrap_or(&0);
+
+        let mut scatter_ctx = ChartBuilder::on(root)
+            .margin(20_i32)
+            .x_label_area_size(40_i32)
+            .y_label_area_size(40_i32)
+            .build_cartesian_2d(0..max_x_value, 0.0..max_y_value)?;
+
+        scatter_ctx
+            .configure_mesh()
+            .y_desc(format!("{} within distance threshold", self.stations_desc))
+            .x_desc("Distance threshold")
+            .x_labels((max_x_value / 100) as usize)
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        scatter_ctx.draw_series(data.iter().map(
+            |(distance, n_stations, _)| {
+                let n_stations_quartiles = Quartiles::new(n_stations);
+                Boxplot::new_vertical(*distance, &n_stations_quartiles)
+                    .width(20)
+            },
+        ))?;
+
+        scatter_ctx.draw_series(data.iter().flat_map(
+            |(max_dist, _, outliers)| {
+                outliers.iter().map(|y_value| {
+                    Circle::new(
+                        (*max_dist, *y_value as f32),
+                        2_i32,
+                        GREY.filled(),
+                    )
+                })
+            },
+        ))?;
+
+        root.present()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,63 @@
+use crate::Inputs;
+use src::{
+    catchments, save_catchments, Catchment, Metric, PopulationPoint, Search,
+    Station, StationTree,
+};
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    distance: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let c = Catchments {
+        out_file,
+        distance,
+        stations: inputs.stations()?,
+        metric: inputs.metric(network.as_ref()),
+    };
+    c.search_to_file(&tree, &points)
+}
+
+struct Catchments<'a> {
+    out_file: &'a str,
+    distance: f64,
+    /// The stations in the order of their IDs, for their names and lat/lon
+    stations: Vec<Station>,
+    metric: Metric<'a>,
+}
+
+impl Search<Vec<Catchment>> for Catchments<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let result = self.search(tree, points, self.distance);
+        let geojson = save_catchments(self.out_file, &result)?;
+        eprintln!("wrote {} and {}", self.out_file, geojson);
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<Catchment> {
+        eprintln!("searching stations within {} m...", max_distance);
+        let within = self.metric.stations_within(tree, points, max_distance);
+
+        eprintln!("searching nearest stations...");
+        let nearest: Vec<_> = self
+            .metric
+            .nearest_stations(tree, points)
+            .into_iter()
+            .map(|nearest| nearest.map(|(station, _)| station.id))
+            .collect();
+
+        catchments(&self.stations, points, &within, &nearest)
+    }
+}
@@ -0,0 +1,145 @@
+use csv::StringRecord;
+use src::{
+    column_index, load_boundaries, load_regions, parse_field, CsvStream,
+    PolygonIndex, PpColumns, RegionProperties, REGION_ID, REGION_NAME,
+};
+
+pub fn run(
+    boundaries: &str,
+    pp: &str,
+    out_path: &str,
+    columns: &PpColumns,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let polygons = PolygonIndex::new(load_boundaries(boundaries)?);
+
+    // the national file can be larger than RAM, so it is streamed in chunks
+    let mut stream = CsvStream::open(pp)?;
+    let headers = stream.headers()?;
+    let lon = column_index(&headers, &columns.lon)?;
+    let lat = column_index(&headers, &columns.lat)?;
+
+    let stats = stream.par_filter_map(out_path, &headers, |record| {
+        let inside = process(&record, &polygons, (lon, lat), columns)?;
+        Ok(inside.then_some(record))
+    })?;
+    eprintln!("kept {} of {} points", stats.written, stats.read);
+    Ok(())
+}
+
+/// Like run, but appends the ID and name of the region that contains every
+/// kept point, as the region_id and region_name columns
+pub fn run_with_regions(
+    boundaries: &str,
+    pp: &str,
+    out_path: &str,
+    columns: &PpColumns,
+    properties: &RegionProperties,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (geometries, regions) = load_regions(boundaries, properties)?;
+    let polygons = PolygonIndex::new(geometries);
+
+    let mut stream = CsvStream::open(pp)?;
+    let mut headers = stream.headers()?;
+    let lon = column_index(&headers, &columns.lon)?;
+    let lat = column_index(&headers, &columns.lat)?;
+    headers.push_field(REGION_ID);
+    headers.push_field(REGION_NAME);
+
+    let stats = stream.par_filter_map(out_path, &headers, |mut record| {
+        let Some(region) = locate(&record, &polygons, (lon, lat), columns)?
+        else {
+            return Ok(None);
+        };
+        record.push_field(&regions[region].id);
+        record.push_field(&regions[region].name);
+        Ok(Some(record))
+    })?;
+    eprintln!("kept {} of {} points", stats.written, stats.read);
+    Ok(())
+}
+
+fn process(
+    record: &StringRecord,
+    polygons: &PolygonIndex,
+    lon_lat: (usize, usize),
+    columns: &PpColumns,
+) -> Result<bool, String> {
+    Ok(locate(record, polygons, lon_lat, columns)?.is_some())
+}
+
+/// The position of the first boundary feature that contains the point. The
+/// columns name the fields in the errors
+fn locate(
+    record: &StringRecord,
+    polygons: &PolygonIndex,
+    (lon, lat): (usize, usize),
+    columns: &PpColumns,
+) -> Result<Option<usize>, String> {
+    // lat is y, lon is x
+    let lon = parse_field(record, lon, &columns.lon)?;
+    let lat = parse_field(record, lat, &columns.lat)?;
+    let point = geo::Point::new(lon, lat);
+
+    Ok(polygons.locate(&point))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geo::Contains;
+
+    #[test]
+    fn test_london_polygons() {
+        let polygons =
+            load_boundaries("../data/london boundaries/london.geojson")
+                .unwrap();
+        let p = geo::Point::new(-0.1270, 51.4475);
+        assert!(polygons.contains(&p));
+    }
+
+    fn record(line: &str) -> StringRecord {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(line.as_bytes());
+        reader.records().next().unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_london_process() {
+        let polygons = PolygonIndex::new(
+            load_boundaries("../data/london boundaries/london.geojson")
+                .unwrap(),
+        );
+
+        let columns = PpColumns::default();
+        let line =
+            r#""51.5781944444857","-0.24125000000019298","9.821008556019821""#;
+
+        // london is lat, lon
+        assert!(process(&record(line), &polygons, (1, 0), &columns).unwrap());
+    }
+
+    #[test]
+    fn test_tokyo_polygons() {
+        let polygons =
+            load_boundaries("../data/tokyo boundaries/clipped.geojson")
+                .unwrap();
+        let p = geo::Point::new(139.689, 35.682);
+        assert!(polygons.contains(&p));
+    }
+
+    #[test]
+    fn test_tokyo_process() {
+        let polygons = PolygonIndex::new(
+            load_boundaries("../data/tokyo boundaries/clipped.geojson")
+                .unwrap(),
+        );
+
+        let columns = PpColumns::default();
+        let line =
+            r#""139.80944444445794","35.66361111110322","17.34286880493164""#;
+
+        // tokyo is lon, lat
+        assert!(process(&record(line), &polygons, (0, 1), &columns).unwrap());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::Inputs;
+use plotters::prelude::Quartiles;
+use src::{two_step_fca, Decay, Metric, PopulationPoint, Search, StationTree};
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    radius: f64,
+    decay: Decay,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let supply = inputs.stations()?.iter().map(|s| s.weight).collect();
+    let f = TwoStepFca {
+        out_file,
+        radius,
+        decay,
+        supply,
+        metric: inputs.metric(network.as_ref()),
+    };
+    f.search_to_file(&tree, &points)
+}
+
+struct TwoStepFca<'a> {
+    out_file: &'a str,
+    radius: f64,
+    decay: Decay,
+    /// The supply of every station by ID, its weight
+    supply: Vec<f64>,
+    metric: Metric<'a>,
+}
+
+impl TwoStepFca<'_> {
+    fn write(
+        &self,
+        points: &[PopulationPoint],
+        scores: &[f64],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = csv::Writer::from_path(self.out_file)
+            .map_err(|e| format!("cannot write {}: {}", self.out_file, e))?;
+        writer.write_record(["x", "y", "pop", "score"])?;
+        for (p, score) in points.iter().zip(scores) {
+            writer.write_record([
+                p.x.to_string(),
+                p.y.to_string(),
+                p.pop.to_string(),
+                score.to_string(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Search<Vec<f64>> for TwoStepFca<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let scores = self.search(tree, points, self.radius);
+        self.write(points, &scores)?;
+        eprintln!("wrote {}", self.out_file);
+
+        // the summary is left out when there is nothing to summarize, as the
+        // mean has no population to divide by and there are no quartiles
+        let total_pop: f64 = points.iter().map(|p| p.pop).sum();
+        if total_pop > 0.0 {
+            let mean = points
+                .iter()
+                .zip(&scores)
+                .map(|(p, score)| p.pop * score)
+                .sum::<f64>()
+                / total_pop;
+            eprintln!("population-weighted mean score: {}", mean);
+        }
+        if !scores.is_empty() {
+            let quartiles = Quartiles::new(&scores).values();
+            eprintln!(
+                "Q1, median and Q3 of the points: {:?}",
+                &quartiles[1..4]
+            );
+        }
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<f64> {
+        eprintln!("searching stations within {} m...", max_distance);
+        let within = self.metric.stations_within(tree, points, max_distance);
+        two_step_fca(points, &within, &self.supply, self.decay)
+    }
+}
@@ -0,0 +1,135 @@
+use crate::Inputs;
+use src::{
+    lisa, morans_i, save_lisa, two_step_fca, Cluster, Decay, DistanceModel,
+    Metric, Neighbours, PopulationPoint, Search, SpatialWeights, StationTree,
+};
+
+/// The access of every population point to test for autocorrelation
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Variable {
+    /// The distance to the nearest station
+    Nearest,
+    /// The number of stations within the distance, or the sum of their
+    /// weights
+    Within,
+    /// The two-step floating catchment area score, with the distance as
+    /// the radius
+    Fca,
+}
+
+/// How the autocorrelation is tested
+pub struct Options {
+    pub variable: Variable,
+    pub distance: f64,
+    pub neighbours: Neighbours,
+    pub permutations: usize,
+    pub significance: f64,
+    pub seed: u64,
+}
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    options: Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let supply = inputs.stations()?.iter().map(|s| s.weight).collect();
+    let a = Autocorrelation {
+        out_file,
+        options,
+        supply,
+        model: inputs.model,
+        metric: inputs.metric(network.as_ref()),
+    };
+    a.search_to_file(&tree, &points)
+}
+
+struct Autocorrelation<'a> {
+    out_file: &'a str,
+    options: Options,
+    /// The supply of every station by ID for fca, its weight
+    supply: Vec<f64>,
+    /// Where the points are placed to find their neighbours
+    model: DistanceModel,
+    metric: Metric<'a>,
+}
+
+impl Autocorrelation<'_> {
+    fn test(
+        &self,
+        points: &[PopulationPoint],
+        values: &[f64],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(i) = values.iter().position(|v| !v.is_finite()) {
+            return Err(format!(
+                "point {} has no station to measure its access to",
+                i
+            )
+            .into());
+        }
+        let Options {
+            neighbours,
+            permutations,
+            significance,
+            seed,
+            ..
+        } = self.options;
+
+        eprintln!("finding neighbours...");
+        let weights = SpatialWeights::new(points, self.model, neighbours);
+        let isolates = weights.isolates();
+        if isolates > 0 {
+            eprintln!("{} points have no neighbours", isolates);
+        }
+
+        eprintln!("permuting {} times...", permutations);
+        let global = morans_i(values, &weights, permutations, seed)?;
+        let lisas = lisa(values, &weights, permutations, significance, seed)?;
+        let geojson = save_lisa(self.out_file, points, values, &lisas)?;
+        eprintln!("wrote {} and {}", self.out_file, geojson);
+
+        eprintln!(
+            "Moran's I: {} (expected {}), p-value: {}",
+            global.i, global.expected, global.p_value
+        );
+        for cluster in Cluster::ALL {
+            let n = lisas.iter().filter(|l| l.cluster == cluster).count();
+            eprintln!("{}: {} points", cluster.name(), n);
+        }
+        Ok(())
+    }
+}
+
+impl Search<Vec<f64>> for Autocorrelation<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let values = self.search(tree, points, self.options.distance);
+        self.test(points, &values)
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<f64> {
+        eprintln!("searching...");
+        match self.options.variable {
+            Variable::Nearest => self.metric.nearest_distances(tree, points),
+            Variable::Within => self
+                .metric
+                .sum_weights_within_thresholds(tree, points, &[max_distance])
+                .swap_remove(0),
+            Variable::Fca => {
+                let within =
+                    self.metric.stations_within(tree, points, max_distance);
+                two_step_fca(points, &within, &self.supply, Decay::Step)
+            }
+        }
+    }
+}
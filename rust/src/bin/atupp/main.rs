@@ -0,0 +1,869 @@
+// Usage: target/release/atupp [--config cities.toml] <command> --city [city]
+// Run with --help for the options of every command
+
+mod box_plot;
+mod catchments;
+mod clip;
+mod fca;
+mod lisa;
+mod nearest;
+mod props;
+mod quadrant_coords;
+mod quadrant_map;
+mod quadrants;
+
+use clap::{Args, Parser, Subcommand};
+use src::{
+    cache_population_points, cache_stations, extract_stations, load_gtfs,
+    load_population_points_cached, load_stations_cached, reproject_csv,
+    save_gtfs_stations, save_stations, to_crs, Cities, City, Decay,
+    DistanceModel, GtfsOptions, Metric, Mode, Neighbours, Network,
+    PopulationPoint, Quadrant, ServiceDay, Split, Splits, Station,
+    StationOptions, StationTree, WalkGraph, Window, CITIES_PATH,
+};
+use std::path::Path;
+use std::process::ExitCode;
+
+/// Analysing transportation using population points
+#[derive(Parser)]
+#[command(name = "atupp")]
+struct Cli {
+    /// The city registry
+    #[arg(long, global = true, default_value = CITIES_PATH)]
+    config: String,
+
+    /// How distances are measured: planar (x and y as they are), mercator
+    /// (x and y in EPSG:3857, corrected for the scale factor), haversine or
+    /// geodesic (lat and lon). Distance thresholds are always in ground
+    /// meters, but planar is only correct for a local projection
+    #[arg(long, global = true, default_value = "geodesic")]
+    distance_model: DistanceModel,
+
+    /// Measure walking distances along the pedestrian network of the osm
+    /// extract in the registry, instead of straight lines. Points and
+    /// stations are snapped to the nearest node of the network
+    #[arg(long, global = true)]
+    network: bool,
+
+    /// The station column to weight the stations by, such as
+    /// departures_per_hour or lines from the gtfs command, instead of the
+    /// weight in station_columns of the registry. The box plot and the
+    /// quadrants then sum the weights of the stations instead of counting
+    /// them
+    #[arg(long, global = true)]
+    weight: Option<String>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Clip the national population points to the city boundaries
+    Clip {
+        #[command(flatten)]
+        common: Common,
+        /// Append the ID and name of the region (borough, ward,
+        /// prefecture...) that contains every point, from the boundary
+        /// feature properties in region_properties of the registry
+        #[arg(long)]
+        regions: bool,
+    },
+    /// Reproject the clipped population points or the stations from lat/lon
+    /// into the CRS of the city, adding x and y columns
+    Reproject {
+        #[command(flatten)]
+        common: Common,
+        /// The file to reproject
+        #[arg(value_enum)]
+        layer: Layer,
+        /// The target CRS, instead of the one in the registry
+        #[arg(long)]
+        crs: Option<String>,
+    },
+    /// Extract the stations from the osm extract, merging the elements of
+    /// every station into one, and write them with x and y in the CRS of
+    /// the city
+    OsmStations {
+        #[command(flatten)]
+        common: Common,
+        /// Also write every subway entrance, named after its station
+        #[arg(long)]
+        entrances: bool,
+        /// The tag to name the stations from
+        #[arg(long, default_value = "name")]
+        name_tag: String,
+    },
+    /// Read the stations from a GTFS feed, with their modes, number of lines
+    /// and departures per hour, and write them with x and y in the CRS of
+    /// the city
+    Gtfs {
+        #[command(flatten)]
+        common: Common,
+        /// The GTFS zip to read instead of the one in the registry
+        #[arg(long)]
+        feed: Option<String>,
+        /// The services to count: a weekday (monday...sunday) of the
+        /// regular timetable, or a YYYYMMDD date, which also applies the
+        /// exceptions in calendar_dates.txt
+        #[arg(long, default_value = "monday")]
+        day: ServiceDay,
+        /// The hours to count the departures in. The trips of the day
+        /// before count after midnight (from their times past 24:00:00),
+        /// and the last stop of a trip isn't a departure
+        #[arg(long, default_value = "07:00-10:00")]
+        window: Window,
+        /// The modes of the routes to keep: tube, rail, tram, bus, other
+        #[arg(long, value_delimiter = ',', default_value = "tube,rail,tram")]
+        modes: Vec<Mode>,
+    },
+    /// Convert the population points and stations in meters into binary
+    /// caches next to them, which every analysis reads instead of the csv
+    /// while they are fresh
+    Cache {
+        #[command(flatten)]
+        common: Common,
+    },
+    /// Proportion of the city population within each distance of a station
+    Props {
+        #[command(flatten)]
+        common: Common,
+        #[command(flatten)]
+        distances: DistancesArg,
+        /// Write the exact cumulative distribution, with a row for every
+        /// distinct distance to the nearest station, instead of --distances
+        #[arg(long)]
+        ecdf: bool,
+        /// Write the proportions of every region, as tagged by
+        /// clip --regions, in a long csv of region, max_dist, pop_within,
+        /// region_pop and prop
+        #[arg(long)]
+        regions: bool,
+        /// Weight the population by the distance to its nearest station
+        /// instead of a hard cutoff, with linear:MAX, exponential:SCALE,
+        /// logistic:MIDPOINT:STEEPNESS, gaussian:SIGMA or power:BETA. The
+        /// proportions rise to the coverage index, which is printed (for
+        /// every region with --regions)
+        #[arg(long)]
+        decay: Option<Decay>,
+    },
+    /// The nearest station of every population point, with its ID (the row
+    /// in the stations file), name and distance
+    Nearest {
+        #[command(flatten)]
+        common: Common,
+        /// Leave the station empty for points further than this, in meters
+        #[arg(long)]
+        max_distance: Option<f64>,
+    },
+    /// The population that depends on every station: within the distance,
+    /// counted for every station or split between them, and for which it is
+    /// the nearest station. Written as a csv and a GeoJSON next to it
+    Catchments {
+        #[command(flatten)]
+        common: Common,
+        /// The catchment distance in meters
+        #[arg(long, default_value = "1000")]
+        distance: f64,
+    },
+    /// Two-step floating catchment area accessibility of every population
+    /// point: the supply of the stations (their weight) shared out between
+    /// the population within their catchments
+    Fca {
+        #[command(flatten)]
+        common: Common,
+        /// The catchment radius in meters
+        #[arg(long, default_value = "1400")]
+        radius: f64,
+        /// How the stations and points within the radius are weighted by
+        /// their distance: step (the classic 2SFCA), or a decay (the
+        /// enhanced E2SFCA) like those of props --decay
+        #[arg(long, default_value = "step")]
+        decay: Decay,
+    },
+    /// Spatial autocorrelation of the access of the population points: global
+    /// Moran's I, and the local Moran's I (LISA) of every point with its
+    /// cluster, written as a csv and a GeoJSON next to it
+    Lisa {
+        #[command(flatten)]
+        common: Common,
+        /// The access to test: the distance to the nearest station, the
+        /// stations within --distance, or the fca score with --distance as
+        /// the radius
+        #[arg(long, value_enum, default_value = "within")]
+        variable: lisa::Variable,
+        /// The distance in meters for within and fca
+        #[arg(long, default_value = "1400")]
+        distance: f64,
+        /// The neighbours of every point: the k nearest points (knn:K), or
+        /// every point within a distance band in meters (band:METERS)
+        #[arg(long, default_value = "knn:8")]
+        neighbours: Neighbours,
+        /// The number of random permutations for the p-values
+        #[arg(long, default_value = "999")]
+        permutations: usize,
+        /// The largest p-value of a point in a cluster
+        #[arg(long, default_value = "0.05")]
+        significance: f64,
+        /// The seed of the permutations, for reproducible p-values
+        #[arg(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Bar chart of the proportions of the population within each distance
+    /// of a station, for every city, or their exact distributions as steps
+    PropsPlot {
+        /// The cities in the registry to compare
+        #[arg(long, value_delimiter = ',', default_value = "london,tokyo")]
+        cities: Vec<String>,
+        #[command(flatten)]
+        distances: DistancesArg,
+        /// Plot the exact cumulative distributions instead of --distances
+        #[arg(long)]
+        ecdf: bool,
+        /// Weight the population by the distance to its nearest station,
+        /// like props --decay
+        #[arg(long)]
+        decay: Option<Decay>,
+        /// Where to write the chart
+        #[arg(long, default_value = "../out/props.png")]
+        out: String,
+    },
+    /// Box plot of the number of stations within each distance of a point
+    Box {
+        #[command(flatten)]
+        common: Common,
+        #[command(flatten)]
+        distances: DistancesArg,
+    },
+    /// Scatterplot of population against the number of stations nearby,
+    /// split into quadrants. One plot per distance
+    Quadrants {
+        #[command(flatten)]
+        common: Common,
+        #[arg(long, default_value = "1400", value_parser = parse_distances)]
+        distances: Distances,
+        #[command(flatten)]
+        splits: SplitsArg,
+    },
+    /// Coordinates of the population points in a quadrant
+    QuadrantCoords {
+        #[command(flatten)]
+        common: Common,
+        #[arg(long, default_value = "1400", value_parser = parse_distances)]
+        distances: Distances,
+        /// The quadrant to output: red, orange, blue or green
+        #[arg(long)]
+        point_type: Quadrant,
+        #[command(flatten)]
+        splits: SplitsArg,
+    },
+    /// Map of every population point, colored by its quadrant over a grey
+    /// background of all points
+    QuadrantMap {
+        #[command(flatten)]
+        common: Common,
+        #[arg(long, default_value = "1400", value_parser = parse_distances)]
+        distances: Distances,
+        /// Draw every quadrant in its own panel, over the other points in
+        /// grey
+        #[arg(long)]
+        facet: bool,
+        /// Outline the city boundaries from the registry
+        #[arg(long)]
+        show_boundary: bool,
+        /// Mark every station
+        #[arg(long)]
+        show_stations: bool,
+        /// The width of the image in pixels. The height follows from the
+        /// extent of the city
+        #[arg(long, default_value = "2048")]
+        width: u32,
+        /// The radius of every point in pixels, or 0 for a single pixel
+        #[arg(long, default_value = "1")]
+        point_size: u32,
+        #[command(flatten)]
+        splits: SplitsArg,
+    },
+}
+
+/// Flags shared by every command
+#[derive(Args)]
+struct Common {
+    /// A city in the registry
+    #[arg(long)]
+    city: String,
+
+    /// Population points to read instead of the ones in the registry.
+    /// For clip, this is the national file, for reproject the clipped file,
+    /// otherwise the file in meters
+    #[arg(long)]
+    pp: Option<String>,
+
+    /// Stations to read instead of the ones in the registry. For
+    /// reproject, this is the file in lat/lon, otherwise the file in meters
+    #[arg(long)]
+    stations: Option<String>,
+
+    /// OSM extract to read the walking network from, instead of the one in
+    /// the registry
+    #[arg(long)]
+    osm: Option<String>,
+
+    /// Where to write the output. Every command has its own default
+    #[arg(long)]
+    out: Option<String>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum Layer {
+    Pp,
+    Stations,
+}
+
+#[derive(Args)]
+struct DistancesArg {
+    /// Distance thresholds in meters, either as a list (500,1000,1400)
+    /// or as an inclusive range with a step (start:end:step)
+    #[arg(long, default_value = "100:3000:100", value_parser = parse_distances)]
+    distances: Distances,
+}
+
+#[derive(Args)]
+struct SplitsArg {
+    /// Where the quadrants split the population of the points:
+    /// percentile:P, weighted:P (the percentile of the population instead
+    /// of the points), mean, median, jenks (the natural break) or a number
+    #[arg(long, default_value = "percentile:75")]
+    pop_split: Split,
+    /// Where the quadrants split the stations near the points, like
+    /// --pop-split. weighted:P weights the points by their population
+    #[arg(long, default_value = "percentile:75")]
+    stations_split: Split,
+}
+
+impl SplitsArg {
+    fn splits(&self) -> Splits {
+        Splits {
+            pop: self.pop_split,
+            n_stations: self.stations_split,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Distances(Vec<f64>);
+
+fn parse_distances(s: &str) -> Result<Distances, String> {
+    let parse = |x: &str| {
+        x.trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|d| *d > 0.0)
+            .ok_or_else(|| format!("`{}` is not a positive distance", x))
+    };
+
+    let distances =
+        if let [start, end, step] = s.split(':').collect::<Vec<_>>()[..] {
+            let (start, end, step) = (parse(start)?, parse(end)?, parse(step)?);
+            if end < start {
+                return Err(format!("the range `{}` ends before it starts", s));
+            }
+            // (end - start) / step can fall just short of a whole number,
+            // which would drop the end
+            let n = ((end - start) / step + 1e-9).floor() as usize;
+            (0..=n)
+                .map(|i| (start + i as f64 * step).min(end))
+                .collect()
+        } else {
+            s.split(',').map(parse).collect::<Result<Vec<_>, _>>()?
+        };
+
+    if distances.is_empty() {
+        return Err("no distances given".into());
+    }
+    Ok(Distances(distances))
+}
+
+/// The city, population point and station paths after the overrides
+pub struct Inputs {
+    name: String,
+    city: City,
+    pp: String,
+    stations: String,
+    model: DistanceModel,
+    /// The OSM extract of the walking network, if the distances are walked
+    osm: Option<String>,
+}
+
+/// The station tree and the population points that every analysis needs
+type Loaded = (StationTree, Vec<PopulationPoint>);
+
+impl Inputs {
+    /// Build the station tree and read the population points
+    pub fn load(&self) -> Result<Loaded, Box<dyn std::error::Error>> {
+        if self.model == DistanceModel::Mercator && self.city.crs != "EPSG:3857"
+        {
+            return Err(format!(
+                "the mercator distance model needs the files in EPSG:3857, \
+                 but {} is in {}",
+                self.name, self.city.crs
+            )
+            .into());
+        }
+
+        eprintln!("loading stations...");
+        let stations = self.stations()?;
+
+        eprintln!("building tree...");
+        let tree = self.model.station_tree(&stations);
+
+        // the pp file is just a few hundred MB, which can fit into RAM
+        eprintln!("reading population points...");
+        let points = load_population_points_cached(
+            &self.pp,
+            &self.city.pp_columns,
+            &self.city.crs,
+        )?;
+
+        Ok((tree, points))
+    }
+
+    /// The walking network with the stations snapped onto it, if the
+    /// distances are walked. The stations are read again, which is cheap
+    /// next to reading the extract
+    pub fn network(
+        &self,
+    ) -> Result<Option<Network>, Box<dyn std::error::Error>> {
+        let Some(osm) = &self.osm else {
+            return Ok(None);
+        };
+        eprintln!("reading walking network from {}...", osm);
+        let graph = WalkGraph::load(osm)?;
+        eprintln!("{} nodes, snapping stations...", graph.len());
+        Ok(Some(Network::new(graph, &self.stations()?)))
+    }
+
+    /// The stations in meters, in the order of their IDs
+    pub fn stations(&self) -> Result<Vec<Station>, Box<dyn std::error::Error>> {
+        load_stations_cached(
+            &self.stations,
+            &self.city.station_columns,
+            &self.city.crs,
+        )
+    }
+
+    /// How the analyses measure distances, given the network from
+    /// Inputs::network
+    pub fn metric<'a>(&self, network: Option<&'a Network>) -> Metric<'a> {
+        Metric::new(self.model, network)
+    }
+
+    /// What the stations near a point add up to, for the axis descriptions
+    pub fn stations_desc(&self) -> String {
+        match &self.city.station_columns.weight {
+            Some(weight) => format!("Sum of station {}", weight),
+            None => "Number of stations".into(),
+        }
+    }
+}
+
+impl Common {
+    fn inputs(
+        &self,
+        cities: &Cities,
+        model: DistanceModel,
+        network: bool,
+        weight: Option<&str>,
+    ) -> Result<Inputs, String> {
+        let mut city =
+            cities.get(&self.city).map_err(|e| e.to_string())?.clone();
+        if let Some(weight) = weight {
+            city.station_columns.weight = Some(weight.into());
+        }
+        let osm = if network {
+            let osm = self.osm.as_ref().or(city.osm.as_ref());
+            let osm = osm.ok_or_else(|| {
+                format!("{} has no osm extract for --network", self.city)
+            })?;
+            Some(osm.clone())
+        } else {
+            None
+        };
+        Ok(Inputs {
+            name: self.city.clone(),
+            pp: self.pp.clone().unwrap_or_else(|| city.pp_meters.clone()),
+            stations: self
+                .stations
+                .clone()
+                .unwrap_or_else(|| city.stations_meters.clone()),
+            city,
+            model,
+            osm,
+        })
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let cities = Cities::load(&cli.config)?;
+    // only the commands that measure distances walk them with --network
+    let inputs_of = |common: &Common, measures: bool| {
+        common.inputs(
+            &cities,
+            cli.distance_model,
+            cli.network && measures,
+            cli.weight.as_deref(),
+        )
+    };
+
+    match cli.command {
+        Command::Clip { common, regions } => {
+            let inputs = inputs_of(&common, false)?;
+            let city = &inputs.city;
+            let pp = common.pp.as_ref().unwrap_or(&city.national_pp);
+            let out = common.out.as_ref().unwrap_or(&city.pp);
+            if !regions {
+                return clip::run(&city.boundaries, pp, out, &city.pp_columns);
+            }
+            let properties =
+                city.region_properties.as_ref().ok_or_else(|| {
+                    format!("{} has no region_properties", inputs.name)
+                })?;
+            clip::run_with_regions(
+                &city.boundaries,
+                pp,
+                out,
+                &city.pp_columns,
+                properties,
+            )
+        }
+        Command::Reproject { common, layer, crs } => {
+            let city = inputs_of(&common, false)?.city;
+            let crs = crs.unwrap_or(city.crs);
+            let (input, out, lon, lat) = match layer {
+                Layer::Pp => (
+                    common.pp.unwrap_or(city.pp),
+                    city.pp_meters,
+                    city.pp_columns.lon,
+                    city.pp_columns.lat,
+                ),
+                Layer::Stations => (
+                    common.stations.unwrap_or(city.stations),
+                    city.stations_meters,
+                    city.station_columns.lon,
+                    city.station_columns.lat,
+                ),
+            };
+            let out = common.out.unwrap_or(out);
+            eprintln!("reprojecting {} to {}...", input, crs);
+            let n = reproject_csv(&input, &out, &lon, &lat, &crs)?;
+            eprintln!("wrote {} rows to {}", n, out);
+            Ok(())
+        }
+        Command::OsmStations {
+            common,
+            entrances,
+            name_tag,
+        } => {
+            let city = inputs_of(&common, false)?.city;
+            let osm = common.osm.or(city.osm).ok_or_else(|| {
+                format!("{} has no osm extract in the registry", common.city)
+            })?;
+            let out = common.out.unwrap_or(city.stations_meters);
+            let options = StationOptions {
+                entrances,
+                name_tag,
+            };
+            eprintln!("reading stations from {}...", osm);
+            let stations = extract_stations(&osm, &options)?
+                .into_iter()
+                .map(|s| {
+                    let (x, y) = to_crs(s.lon, s.lat, &city.crs)?;
+                    Ok(Station {
+                        name: s.name,
+                        lat: s.lat,
+                        lon: s.lon,
+                        x,
+                        y,
+                        weight: 1.0,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            save_stations(&out, &stations, &city.station_columns)?;
+            eprintln!("wrote {} stations to {}", stations.len(), out);
+            Ok(())
+        }
+        Command::Gtfs {
+            common,
+            feed,
+            day,
+            window,
+            modes,
+        } => {
+            let city = inputs_of(&common, false)?.city;
+            let feed = feed.or(city.gtfs).ok_or_else(|| {
+                format!("{} has no gtfs feed in the registry", common.city)
+            })?;
+            let out = common.out.unwrap_or(city.stations_meters);
+            let options = GtfsOptions { day, window, modes };
+            eprintln!("reading {}...", feed);
+            let stations = load_gtfs(&feed, &options)?;
+            let xys = stations
+                .iter()
+                .map(|s| to_crs(s.lon, s.lat, &city.crs))
+                .collect::<Result<Vec<_>, _>>()?;
+            save_gtfs_stations(&out, &stations, &xys, &city.station_columns)?;
+            eprintln!("wrote {} stations to {}", stations.len(), out);
+            Ok(())
+        }
+        Command::Cache { common } => {
+            let inputs = inputs_of(&common, false)?;
+            let city = &inputs.city;
+            let path = cache_population_points(
+                &inputs.pp,
+                &city.pp_columns,
+                &city.crs,
+            )?;
+            eprintln!("wrote {}", path.display());
+            let path = cache_stations(
+                &inputs.stations,
+                &city.station_columns,
+                &city.crs,
+            )?;
+            eprintln!("wrote {}", path.display());
+            Ok(())
+        }
+        Command::Props {
+            common,
+            distances,
+            ecdf,
+            regions,
+            decay,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common.out.unwrap_or_else(|| {
+                let suffix = if regions { "region_props" } else { "props" };
+                format!("../data/{}_{}.csv", inputs.name, suffix)
+            });
+            props::run(
+                &inputs,
+                &out,
+                &distances.distances.0,
+                ecdf,
+                regions,
+                decay,
+            )
+        }
+        Command::PropsPlot {
+            cities: names,
+            distances,
+            ecdf,
+            decay,
+            out,
+        } => {
+            let inputs = names
+                .into_iter()
+                .map(|city| {
+                    let common = Common {
+                        city,
+                        pp: None,
+                        stations: None,
+                        osm: None,
+                        out: None,
+                    };
+                    inputs_of(&common, true)
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            props::run_plot(&inputs, &out, &distances.distances.0, ecdf, decay)
+        }
+        Command::Nearest {
+            common,
+            max_distance,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common.out.unwrap_or_else(|| {
+                format!("../data/{}_nearest.csv", inputs.name)
+            });
+            let max_distance = max_distance.unwrap_or(f64::INFINITY);
+            nearest::run(&inputs, &out, max_distance)
+        }
+        Command::Catchments { common, distance } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common.out.unwrap_or_else(|| {
+                format!("../data/{}_catchments.csv", inputs.name)
+            });
+            catchments::run(&inputs, &out, distance)
+        }
+        Command::Fca {
+            common,
+            radius,
+            decay,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common
+                .out
+                .unwrap_or_else(|| format!("../data/{}_fca.csv", inputs.name));
+            fca::run(&inputs, &out, radius, decay)
+        }
+        Command::Lisa {
+            common,
+            variable,
+            distance,
+            neighbours,
+            permutations,
+            significance,
+            seed,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common
+                .out
+                .unwrap_or_else(|| format!("../data/{}_lisa.csv", inputs.name));
+            let options = lisa::Options {
+                variable,
+                distance,
+                neighbours,
+                permutations,
+                significance,
+                seed,
+            };
+            lisa::run(&inputs, &out, options)
+        }
+        Command::Box { common, distances } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common
+                .out
+                .unwrap_or_else(|| format!("../out/{}_box.png", inputs.name));
+            box_plot::run(&inputs, out, &distances.distances.0)
+        }
+        Command::Quadrants {
+            common,
+            distances,
+            splits,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let out = common.out.unwrap_or_else(|| {
+                format!("../out/{}_quadrant.png", inputs.name)
+            });
+            let distances = distances.0;
+            for distance in &distances {
+                // one plot per distance, so number them if there are many
+                let out = if distances.len() == 1 {
+                    out.clone()
+                } else {
+                    with_suffix(&out, &distance.to_string())
+                };
+                quadrants::run(&inputs, out, *distance, splits.splits())?;
+            }
+            Ok(())
+        }
+        Command::QuadrantCoords {
+            common,
+            distances,
+            point_type,
+            splits,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let distance = match distances.0[..] {
+                [distance] => distance,
+                _ => {
+                    return Err("quadrant-coords takes a single distance".into())
+                }
+            };
+            let out = common.out.unwrap_or_else(|| {
+                format!("../data/{}_{}s.csv", inputs.name, point_type.name())
+            });
+            quadrant_coords::run(
+                &inputs,
+                distance,
+                point_type,
+                splits.splits(),
+                &out,
+            )
+        }
+        Command::QuadrantMap {
+            common,
+            distances,
+            facet,
+            show_boundary,
+            show_stations,
+            width,
+            point_size,
+            splits,
+        } => {
+            let inputs = inputs_of(&common, true)?;
+            let distance = match distances.0[..] {
+                [distance] => distance,
+                _ => return Err("quadrant-map takes a single distance".into()),
+            };
+            let out = common.out.unwrap_or_else(|| {
+                format!("../out/{}_quadrants_map.png", inputs.name)
+            });
+            let overlays = quadrant_map::Overlays {
+                facet,
+                boundary: show_boundary,
+                stations: show_stations,
+                width,
+                point_size,
+            };
+            quadrant_map::run(
+                &inputs,
+                &out,
+                distance,
+                splits.splits(),
+                &overlays,
+            )
+        }
+    }
+}
+
+/// Insert a suffix before the extension: a.png -> a_suffix.png
+fn with_suffix(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_distances_range() {
+        let Distances(d) = parse_distances("100:3000:100").unwrap();
+        assert_eq!(d.len(), 30);
+        assert_eq!(d[0], 100.0);
+        assert_eq!(d[29], 3000.0);
+        let Distances(d) = parse_distances("0.1:1:0.1").unwrap();
+        assert_eq!(d.len(), 10);
+        assert_eq!(d[9], 1.0);
+        assert!(parse_distances("1000:100:100").is_err());
+    }
+
+    #[test]
+    fn test_parse_distances_list() {
+        let Distances(d) = parse_distances("500, 1400").unwrap();
+        assert_eq!(d, [500.0, 1400.0]);
+        assert!(parse_distances("500,abc").is_err());
+        assert!(parse_distances("-1").is_err());
+    }
+
+    #[test]
+    fn test_with_suffix() {
+        assert_eq!(with_suffix("../out/a.png", "1400"), "../out/a_1400.png");
+        assert_eq!(with_suffix("../out/a", "1400"), "../out/a_1400");
+    }
+}
@@ -0,0 +1,95 @@
+use crate::Inputs;
+use src::{Metric, PopulationPoint, Search, StationRecord, StationTree};
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    max_distance: f64,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let n = NearestStation {
+        out_file,
+        max_distance,
+        metric: inputs.metric(network.as_ref()),
+    };
+    n.search_to_file(&tree, &points)?;
+    eprintln!("wrote {}", out_file);
+    Ok(())
+}
+
+struct NearestStation<'a> {
+    out_file: &'a str,
+    /// Points further than this from every station have no station
+    max_distance: f64,
+    metric: Metric<'a>,
+}
+
+impl NearestStation<'_> {
+    /// One row per point, in the order of the population points, with empty
+    /// station columns for the points without a station
+    fn write(
+        &self,
+        points: &[PopulationPoint],
+        nearest: &[Option<(&StationRecord, f64)>],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut writer = csv::Writer::from_path(self.out_file)
+            .map_err(|e| format!("cannot write {}: {}", self.out_file, e))?;
+        writer.write_record([
+            "x",
+            "y",
+            "pop",
+            "station_id",
+            "station_name",
+            "distance",
+        ])?;
+        for (p, nearest) in points.iter().zip(nearest) {
+            let (id, name, d) = match nearest {
+                Some((s, d)) if *d <= self.max_distance => {
+                    (s.id.to_string(), s.name.as_str(), d.to_string())
+                }
+                _ => (String::new(), "", String::new()),
+            };
+            writer.write_record([
+                p.x.to_string(),
+                p.y.to_string(),
+                p.pop.to_string(),
+                id,
+                name.to_string(),
+                d,
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Search<Vec<Option<(usize, f64)>>> for NearestStation<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        eprintln!("searching...");
+        let nearest = self.metric.nearest_stations(tree, points);
+        self.write(points, &nearest)
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<Option<(usize, f64)>> {
+        self.metric
+            .nearest_stations(tree, points)
+            .into_iter()
+            .map(|nearest| {
+                let (station, d) =
+                    nearest.filter(|(_, d)| *d <= max_distance)?;
+                Some((station.id, d))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,450 @@
+use crate::Inputs;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use src::{
+    load_point_regions, Chart, CumulativePop, Decay, Draw, Metric,
+    PointRegions, PopulationPoint, Search, StationTree,
+};
+use std::fs;
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    distances: &[f64],
+    ecdf: bool,
+    by_region: bool,
+    decay: Option<Decay>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let regions = if by_region {
+        // the regions aren't in the cache, so they are always read from
+        // the csv
+        eprintln!("reading regions...");
+        let regions = load_point_regions(&inputs.pp).map_err(|e| {
+            format!("{}, the points need clip --regions and reproject", e)
+        })?;
+        if regions.of_point.len() != points.len() {
+            return Err(format!(
+                "{} has {} regions, but {} population points",
+                inputs.pp,
+                regions.of_point.len(),
+                points.len()
+            )
+            .into());
+        }
+        Some(regions)
+    } else {
+        None
+    };
+
+    let o = CumulativeProps {
+        out_file,
+        regions,
+        props: Proportions {
+            city: &inputs.name,
+            distances,
+            ecdf,
+            decay,
+            metric: inputs.metric(network.as_ref()),
+        },
+    };
+    o.search_to_file(&tree, &points)
+}
+
+/// Search the proportions of every city, then plot them into one chart
+pub fn run_plot(
+    cities: &[Inputs],
+    out_file: &str,
+    distances: &[f64],
+    ecdf: bool,
+    decay: Option<Decay>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = vec![];
+    for inputs in cities {
+        eprintln!("{}:", inputs.name);
+        let (tree, points) = inputs.load()?;
+        let network = inputs.network()?;
+        let o = Proportions {
+            city: &inputs.name,
+            distances,
+            ecdf,
+            decay,
+            metric: inputs.metric(network.as_ref()),
+        };
+        data.push((inputs.name.clone(), o.proportions(&tree, &points)));
+    }
+    plot(out_file, distances, ecdf, decay, data)
+}
+
+/// Plot the proportions of every city into one chart: grouped bars for the
+/// distances, or steps for the exact distributions with ecdf
+fn plot(
+    out_file: &str,
+    distances: &[f64],
+    ecdf: bool,
+    decay: Option<Decay>,
+    data: Vec<(String, Vec<(f64, f64)>)>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let chart = PropsChart {
+        out_file,
+        distances,
+        ecdf,
+        weighted: decay.is_some(),
+    };
+    chart.plot(data)?;
+    eprintln!("wrote {}", out_file);
+    Ok(())
+}
+
+/// The proportions of the population within every distance
+struct Proportions<'a> {
+    /// The name of the city, to tell the cities apart in the output
+    city: &'a str,
+    distances: &'a [f64],
+    /// Every distinct distance instead of the distances
+    ecdf: bool,
+    /// Weight the population by the decay of its distance instead of
+    /// counting it fully within every distance
+    decay: Option<Decay>,
+    metric: Metric<'a>,
+}
+
+impl Proportions<'_> {
+    fn cumulative(
+        &self,
+        points: &[PopulationPoint],
+        nearest: &[f64],
+    ) -> CumulativePop {
+        match self.decay {
+            Some(decay) => CumulativePop::with_decay(points, nearest, decay),
+            None => CumulativePop::new(points, nearest),
+        }
+    }
+
+    /// Every distance threshold and the population within it
+    fn rows(&self, cum_pop: &CumulativePop) -> Vec<(f64, f64)> {
+        if self.ecdf {
+            cum_pop.ecdf().collect()
+        } else {
+            self.distances
+                .iter()
+                .map(|max_dist| (*max_dist, cum_pop.pop_within(*max_dist)))
+                .collect()
+        }
+    }
+
+    /// Every distance threshold and the proportion of the population
+    /// within it
+    fn proportions(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Vec<(f64, f64)> {
+        // every threshold only needs the distance to the nearest station,
+        // so search once and sort
+        eprintln!("searching...");
+        let nearest = self.metric.nearest_distances(tree, points);
+        let cum_pop = self.cumulative(points, &nearest);
+        eprintln!("population: {}", cum_pop.total());
+        if self.decay.is_some() {
+            eprintln!("{} coverage index: {}", self.city, cum_pop.coverage());
+        }
+        self.rows(&cum_pop)
+            .into_iter()
+            .map(|(max_dist, pop_within)| {
+                (max_dist, pop_within / cum_pop.total())
+            })
+            .collect()
+    }
+}
+
+/// Write the proportions of a city to a csv
+struct CumulativeProps<'a> {
+    out_file: &'a str,
+    /// Write the proportions of every region instead of the city
+    regions: Option<PointRegions>,
+    props: Proportions<'a>,
+}
+
+impl CumulativeProps<'_> {
+    /// A long csv with a row for every region and distance threshold
+    fn write_regions(
+        &self,
+        regions: &PointRegions,
+        points: &[PopulationPoint],
+        nearest: &[f64],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut region_points = vec![vec![]; regions.regions.len()];
+        let mut region_nearest = vec![vec![]; regions.regions.len()];
+        for ((p, d), region) in
+            points.iter().zip(nearest).zip(&regions.of_point)
+        {
+            region_points[*region].push(*p);
+            region_nearest[*region].push(*d);
+        }
+
+        let mut writer = csv::Writer::from_path(self.out_file)
+            .map_err(|e| format!("cannot write {}: {}", self.out_file, e))?;
+        writer.write_record([
+            "region",
+            "max_dist",
+            "pop_within",
+            "region_pop",
+            "prop",
+        ])?;
+        for (i, region) in regions.regions.iter().enumerate() {
+            let cum_pop =
+                self.props.cumulative(&region_points[i], &region_nearest[i]);
+            if self.props.decay.is_some() {
+                eprintln!(
+                    "{} {} coverage index: {}",
+                    self.props.city,
+                    region.name,
+                    cum_pop.coverage()
+                );
+            }
+            let region_pop = cum_pop.total();
+            for (max_dist, pop_within) in self.props.rows(&cum_pop) {
+                writer.write_record([
+                    region.name.clone(),
+                    max_dist.to_string(),
+                    pop_within.to_string(),
+                    region_pop.to_string(),
+                    (pop_within / region_pop).to_string(),
+                ])?;
+            }
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Search<f64> for CumulativeProps<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(regions) = &self.regions {
+            eprintln!("searching...");
+            let nearest = self.props.metric.nearest_distances(tree, points);
+            return self.write_regions(regions, points, &nearest);
+        }
+
+        let result: Vec<_> = self
+            .props
+            .proportions(tree, points)
+            .into_iter()
+            .map(|(max_dist, prop)| format!("{},{}", max_dist, prop))
+            .collect();
+
+        let joined = "max_dist,prop\n".to_string() + &result.join("\n");
+        fs::write(self.out_file, joined).map_err(|e| {
+            format!("cannot write {}: {}", self.out_file, e).into()
+        })
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> f64 {
+        let nearest = self.props.metric.nearest_distances(tree, points);
+        self.props
+            .cumulative(points, &nearest)
+            .pop_within(max_distance)
+    }
+}
+
+/// The proportions of every city, without the search
+struct PropsChart<'a> {
+    out_file: &'a str,
+    distances: &'a [f64],
+    /// The rows are every distinct distance instead of the distances
+    ecdf: bool,
+    /// The proportions are weighted by a decay
+    weighted: bool,
+}
+
+impl Draw<Vec<(String, Vec<(f64, f64)>)>> for PropsChart<'_> {
+    fn out_file(&self) -> &str {
+        self.out_file
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (1500, 900)
+    }
+
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: Vec<(String, Vec<(f64, f64)>)>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        root.fill(&WHITE)?;
+
+        let y_desc = if self.weighted {
+            "Decay-weighted proportion of population within distance"
+        } else {
+            "Proportion of population within distance of a station"
+        };
+        let max_y_value = data
+            .iter()
+            .flat_map(|(_, rows)| rows.iter().map(|(_, prop)| *prop))
+            .fold(0.0, f64::max)
+            .max(0.01);
+
+        if self.ecdf {
+            self.plot_steps(root, &data, y_desc, max_y_value)?;
+        } else {
+            self.plot_bars(root, &data, y_desc, max_y_value)?;
+        }
+        root.present()?;
+        Ok(())
+    }
+}
+
+impl PropsChart<'_> {
+    /// A group of bars for every distance, with a bar for every city
+    fn plot_bars<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: &[(String, Vec<(f64, f64)>)],
+        y_desc: &str,
+        max_y_value: f64,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let n = self.distances.len();
+        let mut chart = ChartBuilder::on(root)
+            .margin(20_i32)
+            .x_label_area_size(60_i32)
+            .y_label_area_size(60_i32)
+            .build_cartesian_2d(-0.5..(n as f64 - 0.5), 0.0..max_y_value)?;
+
+        // the bars are at the index of their distance
+        let label = |x: &f64| {
+            let i = x.round();
+            if i < 0.0 || (x - i).abs() > 1e-6 {
+                return String::new();
+            }
+            self.distances
+                .get(i as usize)
+                .map_or(String::new(), |d| d.to_string())
+        };
+        chart
+            .configure_mesh()
+            .y_desc(y_desc)
+            .x_desc("Distance")
+            .x_labels(n)
+            .x_label_formatter(&label)
+            .x_label_style(
+                ("sans-serif", 12_i32)
+                    .into_font()
+                    .transform(FontTransform::Rotate270),
+            )
+            .axis_desc_style(("sans-serif", 20_i32))
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        let width = 0.8 / data.len() as f64;
+        for (c, (city, rows)) in data.iter().enumerate() {
+            let color = Palette99::pick(c).to_rgba();
+            chart
+                .draw_series(rows.iter().enumerate().map(|(i, (_, prop))| {
+                    let left = i as f64 - 0.4 + c as f64 * width;
+                    Rectangle::new(
+                        [(left, 0.0), (left + width, *prop)],
+                        color.filled(),
+                    )
+                }))?
+                .label(city)
+                .legend(move |(x, y)| {
+                    Rectangle::new(
+                        [(x, y - 5), (x + 10, y + 5)],
+                        color.filled(),
+                    )
+                });
+        }
+        draw_legend(&mut chart)
+    }
+
+    /// A step line for every city, rising at every distinct distance
+    fn plot_steps<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: &[(String, Vec<(f64, f64)>)],
+        y_desc: &str,
+        max_y_value: f64,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let max_x_value = data
+            .iter()
+            .flat_map(|(_, rows)| rows.iter().map(|(d, _)| *d))
+            .fold(1.0, f64::max);
+        let mut chart = ChartBuilder::on(root)
+            .margin(20_i32)
+            .x_label_area_size(60_i32)
+            .y_label_area_size(60_i32)
+            .build_cartesian_2d(0.0..max_x_value, 0.0..max_y_value)?;
+
+        chart
+            .configure_mesh()
+            .y_desc(y_desc)
+            .x_desc("Distance")
+            .x_label_formatter(&|x| format!("{:.0}", x))
+            .x_label_style(
+                ("sans-serif", 12_i32)
+                    .into_font()
+                    .transform(FontTransform::Rotate270),
+            )
+            .axis_desc_style(("sans-serif", 20_i32))
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        for (c, (city, rows)) in data.iter().enumerate() {
+            let color = Palette99::pick(c).to_rgba();
+            let mut steps = vec![(0.0, 0.0)];
+            for (d, prop) in rows {
+                let previous = steps.last().unwrap().1;
+                steps.push((*d, previous));
+                steps.push((*d, *prop));
+            }
+            chart
+                .draw_series(LineSeries::new(steps, color.stroke_width(2)))?
+                .label(city)
+                .legend(move |(x, y)| {
+                    PathElement::new(
+                        [(x, y), (x + 20, y)],
+                        color.stroke_width(2),
+                    )
+                });
+        }
+        draw_legend(&mut chart)
+    }
+}
+
+fn draw_legend<'a, DB: DrawingBackend + 'a>(
+    chart: &mut Chart<'a, DB>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB::ErrorType: 'static,
+{
+    chart
+        .configure_series_labels()
+        .position(SeriesLabelPosition::UpperLeft)
+        .background_style(WHITE)
+        .border_style(BLACK)
+        .draw()?;
+    Ok(())
+}
@@ -0,0 +1,96 @@
+use crate::Inputs;
+use rayon::prelude::*;
+use src::{
+    Metric, PopulationPoint, Quadrant, Search, Splits, StationTree, Thresholds,
+};
+use std::fs;
+
+pub fn run(
+    inputs: &Inputs,
+    distance_threshold: f64,
+    point_type: Quadrant,
+    splits: Splits,
+    outfile: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+    let metric = inputs.metric(network.as_ref());
+
+    eprintln!("calculating thresholds of population and n stations...");
+    let populations: Vec<_> = points.iter().map(|p| p.pop).collect();
+    let n_stations_vec = metric
+        .sum_weights_within_thresholds(&tree, &points, &[distance_threshold])
+        .swap_remove(0);
+    let thresholds = Thresholds::new(&populations, &n_stations_vec, splits);
+    let [pop_label, n_stations_label] = thresholds.labels();
+    eprintln!("{}, {}", pop_label, n_stations_label);
+
+    let q = QuadrantCoords {
+        thresholds,
+        distance_threshold,
+        point_type,
+        outfile,
+        metric,
+    };
+    q.search_to_file(&tree, &points)
+}
+
+struct QuadrantCoords<'a> {
+    thresholds: Thresholds,
+    distance_threshold: f64,
+    point_type: Quadrant,
+    outfile: &'a str,
+    metric: Metric<'a>,
+}
+
+impl Search<Vec<(f64, f64)>> for QuadrantCoords<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        eprintln!("searching...");
+
+        let xys = self.search(tree, points, self.distance_threshold);
+        // the thresholds are on every row, so that the csv records them
+        let (pop, n_stations) =
+            (self.thresholds.pop, self.thresholds.n_stations);
+        let res: Vec<_> = xys
+            .into_iter()
+            .map(|(x, y)| format!("{},{},{},{}", x, y, pop, n_stations))
+            .collect();
+
+        let joined = "x,y,pop_threshold,n_stations_threshold\n".to_string()
+            + &res.join("\n");
+        fs::write(self.outfile, joined)
+            .map_err(|e| format!("cannot write {}: {}", self.outfile, e).into())
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<(f64, f64)> {
+        let n_stations = self
+            .metric
+            .sum_weights_within_thresholds(tree, points, &[max_distance])
+            .swap_remove(0);
+
+        let pop_within_dist: Vec<_> = points
+            .into_par_iter()
+            .zip(n_stations)
+            .filter_map(|(p, n_stations)| {
+                if self.thresholds.classify(p.pop, n_stations)
+                    == self.point_type
+                {
+                    Some((p.x, p.y))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        pop_within_dist
+    }
+}
@@ -0,0 +1,64 @@
+use crate::Inputs;
+use src::{
+    load_boundaries, reproject_geometries, QuadrantMap, Splits, Thresholds,
+};
+
+/// What to draw besides the points
+pub struct Overlays {
+    pub facet: bool,
+    pub boundary: bool,
+    pub stations: bool,
+    pub width: u32,
+    pub point_size: u32,
+}
+
+pub fn run(
+    inputs: &Inputs,
+    out_file: &str,
+    distance_threshold: f64,
+    splits: Splits,
+    overlays: &Overlays,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+    let metric = inputs.metric(network.as_ref());
+
+    eprintln!("searching...");
+    let populations: Vec<_> = points.iter().map(|p| p.pop).collect();
+    let n_stations = metric
+        .sum_weights_within_thresholds(&tree, &points, &[distance_threshold])
+        .swap_remove(0);
+    let thresholds = Thresholds::new(&populations, &n_stations, splits);
+    let quadrants: Vec<_> = points
+        .iter()
+        .zip(&n_stations)
+        .map(|(p, n)| thresholds.classify(p.pop, *n))
+        .collect();
+
+    let boundary = if overlays.boundary {
+        let boundary = load_boundaries(&inputs.city.boundaries)?;
+        Some(reproject_geometries(&boundary, &inputs.city.crs)?)
+    } else {
+        None
+    };
+    let stations = if overlays.stations {
+        Some(inputs.stations()?)
+    } else {
+        None
+    };
+
+    eprintln!("drawing {} points...", points.len());
+    let map = QuadrantMap {
+        points: &points,
+        quadrants: &quadrants,
+        boundary: boundary.as_ref(),
+        stations: stations.as_deref(),
+        thresholds: Some(&thresholds),
+        facet: overlays.facet,
+        width: overlays.width,
+        point_size: overlays.point_size,
+    };
+    map.save(out_file)?;
+    eprintln!("wrote {}", out_file);
+    Ok(())
+}
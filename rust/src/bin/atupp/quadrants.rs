@@ -0,0 +1,249 @@
+use crate::Inputs;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::full_palette::GREY;
+use src::{
+    plot_hline, plot_vline, Draw, Metric, Plot, PopulationPoint, Search,
+    Splits, StationTree, Thresholds,
+};
+
+pub fn run(
+    inputs: &Inputs,
+    out_filename: String,
+    distance_threshold: f64,
+    splits: Splits,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (tree, points) = inputs.load()?;
+    let network = inputs.network()?;
+
+    let q = Quadrants {
+        out_filename,
+        distance_threshold,
+        splits,
+        metric: inputs.metric(network.as_ref()),
+        stations_desc: inputs.stations_desc(),
+    };
+    q.search_to_plot(&tree, &points)
+}
+
+struct Quadrants<'a> {
+    out_filename: String,
+    distance_threshold: f64,
+    /// Where the quadrants are split
+    splits: Splits,
+    metric: Metric<'a>,
+    /// Number of stations, or the sum of their weights
+    stations_desc: String,
+}
+
+impl Search<Vec<(f64, f64)>> for Quadrants<'_> {
+    fn search_to_file(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // this isn't actually used here, just for debugging
+        eprintln!("searching...");
+
+        let res = self.search(tree, points, self.distance_threshold);
+
+        println!("population,n_stations");
+        for (pop, n_stations) in res {
+            println!("{},{}", pop, n_stations)
+        }
+        Ok(())
+    }
+
+    fn search(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<(f64, f64)> {
+        let n_stations = self
+            .metric
+            .sum_weights_within_thresholds(tree, points, &[max_distance])
+            .swap_remove(0);
+
+        points
+            .iter()
+            .zip(n_stations)
+            .map(|(p, n_stations)| (p.pop, n_stations))
+            .collect()
+    }
+}
+
+impl Plot<Vec<(f64, f64)>, Vec<(f64, f64)>> for Quadrants<'_> {
+    fn search_to_plot(
+        &self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        eprintln!("searching...");
+        let result = self.search(tree, points, self.distance_threshold);
+        self.plot(result)
+    }
+}
+
+impl Draw<Vec<(f64, f64)>> for Quadrants<'_> {
+    fn out_file(&self) -> &str {
+        &self.out_filename
+    }
+
+    fn size(&self) -> (u32, u32) {
+        (1024, 768)
+    }
+
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: Vec<(f64, f64)>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let populations: Vec<_> = data.iter().map(|x| x.0).collect();
+        let n_stations: Vec<_> = data.iter().map(|x| x.1).collect();
+        let thresholds =
+            Thresholds::new(&populations, &n_stations, self.splits);
+
+        root.fill(&WHITE)?;
+        let roots = root.split_by_breakpoints([10_i32], [668_i32]);
+        let left_box_area = &roots[0];
+        let scatterplot_area = &roots[1];
+        let bottom_box_area = &roots[3];
+        //let (upper, bottom_box_area) = root.split_vertically(668);
+        //let (left_box_area, scatterplot_area) = upper.split_horizontally(10);
+
+        let max_x_value = data.iter().map(|x| x.0).fold(f64::NAN, f64::max);
+        let max_y_value = data.iter().map(|x| x.1).fold(f64::NAN, f64::max);
+        let mut scatter_ctx = ChartBuilder::on(scatterplot_area)
+            .margin(20_i32)
+            .x_label_area_size(40_i32)
+            .y_label_area_size(40_i32)
+            .build_cartesian_2d(0_f64..max_x_value, 0_f64..max_y_value)?;
+
+        scatter_ctx
+            .configure_mesh()
+            .y_desc(format!(
+                "{} within {} m of a population point",
+                self.stations_desc, self.distance_threshold
+            ))
+            .axis_desc_style(
+                ("sans-serif", 20_i32).into_text_style(scatterplot_area),
+            )
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        scatter_ctx.draw_series(data.iter().map(|(x, y)| {
+            let color = thresholds.classify(*x, *y).color();
+            Circle::new((*x, *y), 2_i32, color.filled())
+        }))?;
+
+        plot_vline(
+            scatterplot_area,
+            &scatter_ctx,
+            thresholds.pop,
+            0,
+            0,
+            BLUE.stroke_width(1),
+        )?;
+
+        plot_hline(
+            scatterplot_area,
+            &scatter_ctx,
+            thresholds.n_stations,
+            0,
+            max_x_value,
+            BLUE.filled(),
+        )?;
+
+        // the lines have no series of their own, so empty ones list the
+        // thresholds in the legend
+        for label in thresholds.labels() {
+            scatter_ctx
+                .draw_series(std::iter::empty::<Circle<(f64, f64), i32>>())?
+                .label(label)
+                .legend(|(x, y)| {
+                    PathElement::new(
+                        [(x, y), (x + 20, y)],
+                        BLUE.stroke_width(1),
+                    )
+                });
+        }
+        scatter_ctx
+            .configure_series_labels()
+            .position(SeriesLabelPosition::UpperRight)
+            .background_style(WHITE)
+            .border_style(BLACK)
+            .draw()?;
+
+        let mut chart = ChartBuilder::on(bottom_box_area)
+            .margin(20_i32)
+            .margin_top(0)
+            .x_label_area_size(40_i32)
+            .y_label_area_size(40_i32)
+            .build_cartesian_2d(0_f32..(max_x_value as f32), 0..2_i32)?;
+
+        chart
+            .configure_mesh()
+            .x_desc("Population of point")
+            .axis_desc_style(
+                ("sans-serif", 20_i32).into_text_style(bottom_box_area),
+            )
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        let pops: Vec<_> = data.iter().map(|x| x.0).collect();
+        let pop_quartiles = Quartiles::new(&pops);
+        let boxplot = Boxplot::new_horizontal(1, &pop_quartiles).width(20);
+        chart.draw_series([boxplot])?;
+
+        // TODO: copied from stations_within_pp.rs
+        let lower = pop_quartiles.values()[0];
+        let upper = pop_quartiles.values()[4];
+        let outliers = pops.iter().filter(|x| {
+            let x = **x as f32;
+            x < lower || x > upper
+        });
+        chart
+            .draw_series(outliers.map(|x| {
+                Circle::new((*x as f32, 1_i32), 2_i32, GREY.filled())
+            }))?;
+
+        let mut chart = ChartBuilder::on(left_box_area)
+            .margin(20_i32)
+            .margin_right(0_i32)
+            .x_label_area_size(40_i32)
+            .y_label_area_size(40_i32)
+            .build_cartesian_2d(0..2_i32, 0_f32..(max_y_value as f32))?;
+
+        chart
+            .configure_mesh()
+            .disable_x_mesh()
+            .disable_y_mesh()
+            .draw()?;
+
+        let n_stations_quartiles = Quartiles::new(&n_stations);
+        let boxplot = Boxplot::new_vertical(1, &n_stations_quartiles).width(20);
+        chart.draw_series([boxplot])?;
+
+        // TODO: copied from stations_within_pp.rs
+        let lower = n_stations_quartiles.values()[0];
+        let upper = n_stations_quartiles.values()[4];
+        let outliers = n_stations.iter().filter(|x| {
+            let x = **x as f32;
+            x < lower || x > upper
+        });
+        chart
+            .draw_series(outliers.map(|x| {
+                Circle::new((1_i32, *x as f32), 2_i32, GREY.filled())
+            }))?;
+
+        root.present()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use crate::RegionProperties;
+use geo::{BoundingRect, Contains, Geometry, GeometryCollection, Point};
+use geojson::{GeoJson, JsonValue};
+use rstar::primitives::{GeomWithData, Rectangle};
+use rstar::{Envelope as _, RTree, AABB};
+use std::fs;
+
+/// The envelope of a polygon and its position in the index
+type Envelope = GeomWithData<Rectangle<[f64; 2]>, usize>;
+
+/// The boundary polygons in an R* tree of their envelopes, so that a point
+/// is only tested against the few polygons whose envelope contains it.
+/// Gives the same results as GeometryCollection::contains
+pub struct PolygonIndex {
+    /// The envelope of every polygon, to reject points outside all of them
+    bbox: AABB<[f64; 2]>,
+    tree: RTree<Envelope>,
+    /// Every polygon, with multi-polygons and collections split up
+    parts: Vec<Geometry>,
+    /// The position in the collection of the geometry of every part
+    features: Vec<usize>,
+}
+
+/// Split multi-polygons and collections, because a point is contained by
+/// them if any of their members contains it
+fn flatten(
+    geometry: Geometry,
+    feature: usize,
+    out: &mut Vec<(usize, Geometry)>,
+) {
+    match geometry {
+        Geometry::MultiPolygon(mp) => {
+            out.extend(mp.into_iter().map(|p| (feature, Geometry::Polygon(p))))
+        }
+        Geometry::GeometryCollection(gc) => {
+            for g in gc {
+                flatten(g, feature, out);
+            }
+        }
+        g => out.push((feature, g)),
+    }
+}
+
+impl PolygonIndex {
+    pub fn new(collection: GeometryCollection) -> Self {
+        let mut flat = vec![];
+        for (feature, geometry) in collection.into_iter().enumerate() {
+            flatten(geometry, feature, &mut flat);
+        }
+        // empty geometries don't contain anything
+        flat.retain(|(_, g)| g.bounding_rect().is_some());
+
+        let envelopes = flat
+            .iter()
+            .enumerate()
+            .map(|(i, (_, g))| {
+                let rect = g.bounding_rect().unwrap();
+                let (min, max) = (rect.min(), rect.max());
+                Envelope::new(
+                    Rectangle::from_corners([min.x, min.y], [max.x, max.y]),
+                    i,
+                )
+            })
+            .collect();
+
+        let tree = RTree::<Envelope>::bulk_load(envelopes);
+        let (features, parts) = flat.into_iter().unzip();
+        Self {
+            bbox: tree.root().envelope(),
+            tree,
+            parts,
+            features,
+        }
+    }
+
+    /// The position in the collection of the first geometry that contains
+    /// the point
+    pub fn locate(&self, point: &Point) -> Option<usize> {
+        let point_xy = [point.x(), point.y()];
+        if !self.bbox.contains_point(&point_xy) {
+            return None;
+        }
+
+        self.tree
+            .locate_all_at_point(&point_xy)
+            .filter(|envelope| self.parts[envelope.data].contains(point))
+            .map(|envelope| self.features[envelope.data])
+            .min()
+    }
+
+    pub fn contains(&self, point: &Point) -> bool {
+        self.locate(point).is_some()
+    }
+}
+
+/// The columns that the region-tagging clip appends to the population points
+pub const REGION_ID: &str = "region_id";
+pub const REGION_NAME: &str = "region_name";
+
+/// A borough, ward or prefecture, from the properties of a boundary feature
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Region {
+    pub id: String,
+    pub name: String,
+}
+
+/// The geometries of the boundary features, in the same order as their
+/// regions
+pub type Regions = (GeometryCollection, Vec<Region>);
+
+/// Read the features of a boundary GeoJSON, keeping the region of every
+/// feature from the given properties
+pub fn parse_regions(
+    geojson: &str,
+    properties: &RegionProperties,
+) -> Result<Regions, String> {
+    let geojson: GeoJson = geojson.parse().map_err(|e| format!("{}", e))?;
+    let GeoJson::FeatureCollection(collection) = geojson else {
+        return Err("regions need a FeatureCollection".into());
+    };
+
+    let mut geometries = vec![];
+    let mut regions = vec![];
+    for (i, feature) in collection.features.into_iter().enumerate() {
+        let property = |name: &str| match feature.property(name) {
+            Some(JsonValue::String(s)) => Ok(s.clone()),
+            Some(value) => Ok(value.to_string()),
+            None => Err(format!("feature {} has no property `{}`", i, name)),
+        };
+        regions.push(Region {
+            id: property(&properties.id)?,
+            name: property(&properties.name)?,
+        });
+
+        // a feature without a geometry doesn't contain anything, but keeps
+        // the geometries and regions in step
+        let geometry = match feature.geometry {
+            Some(g) => Geometry::try_from(g).map_err(|e| e.to_string())?,
+            None => Geometry::GeometryCollection(GeometryCollection(vec![])),
+        };
+        geometries.push(geometry);
+    }
+    Ok((GeometryCollection(geometries), regions))
+}
+
+/// Read every geometry of a boundary GeoJSON, in lat/lon
+pub fn load_boundaries(
+    path: &str,
+) -> Result<GeometryCollection, Box<dyn std::error::Error>> {
+    let s = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let geojson: GeoJson = s.parse().map_err(|e| format!("{}: {}", path, e))?;
+    let geometry: Geometry =
+        geojson.try_into().map_err(|e| format!("{}: {}", path, e))?;
+    Ok(geometry.into())
+}
+
+pub fn load_regions(
+    path: &str,
+    properties: &RegionProperties,
+) -> Result<Regions, Box<dyn std::error::Error>> {
+    let s = fs::read_to_string(path)
+        .map_err(|e| format!("cannot read {}: {}", path, e))?;
+    parse_regions(&s, properties).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geo::{polygon, MultiPolygon};
+
+    fn collection() -> GeometryCollection {
+        let square = |x: f64, y: f64, size: f64| {
+            polygon![
+                (x: x, y: y),
+                (x: x + size, y: y),
+                (x: x + size, y: y + size),
+                (x: x, y: y + size),
+            ]
+        };
+        let islands = MultiPolygon::new(vec![
+            square(0.0, 0.0, 1.0),
+            square(5.0, 5.0, 1.0),
+        ]);
+        // overlaps the first island, but comes later
+        let big = square(0.5, 0.5, 2.0);
+        GeometryCollection(vec![islands.into(), big.into()])
+    }
+
+    #[test]
+    fn test_locate() {
+        let index = PolygonIndex::new(collection());
+        assert_eq!(index.locate(&Point::new(0.2, 0.2)), Some(0));
+        assert_eq!(index.locate(&Point::new(0.7, 0.7)), Some(0));
+        assert_eq!(index.locate(&Point::new(5.5, 5.5)), Some(0));
+        assert_eq!(index.locate(&Point::new(2.0, 2.0)), Some(1));
+        assert_eq!(index.locate(&Point::new(4.0, 4.0)), None);
+        assert_eq!(index.locate(&Point::new(-9.0, 0.5)), None);
+    }
+
+    #[test]
+    fn test_same_as_collection() {
+        let collection = collection();
+        let index = PolygonIndex::new(collection.clone());
+        for i in 0..70 {
+            for j in 0..70 {
+                let p = Point::new(i as f64 * 0.1 - 0.5, j as f64 * 0.1 - 0.5);
+                assert_eq!(
+                    index.contains(&p),
+                    collection.contains(&p),
+                    "{:?}",
+                    p
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_regions() {
+        let geojson = r#"{"type": "FeatureCollection", "features": [
+            {"type": "Feature", "properties": {"NAME": "Bexley", "CODE": 4},
+             "geometry": {"type": "Polygon", "coordinates":
+                [[[0, 0], [1, 0], [1, 1], [0, 1], [0, 0]]]}},
+            {"type": "Feature", "properties": {"NAME": "Tower Hamlets",
+             "CODE": "E09000030"}, "geometry": null}
+        ]}"#;
+        let properties = RegionProperties {
+            id: "CODE".into(),
+            name: "NAME".into(),
+        };
+        let (geometries, regions) =
+            parse_regions(geojson, &properties).unwrap();
+        assert_eq!(geometries.len(), 2);
+        assert_eq!(regions[0].id, "4");
+        assert_eq!(regions[1].name, "Tower Hamlets");
+
+        let index = PolygonIndex::new(geometries);
+        assert_eq!(index.locate(&Point::new(0.5, 0.5)), Some(0));
+
+        let properties = RegionProperties {
+            id: "GSS_CODE".into(),
+            name: "NAME".into(),
+        };
+        let err = parse_regions(geojson, &properties).unwrap_err();
+        assert_eq!(err, "feature 0 has no property `GSS_CODE`");
+    }
+}
@@ -0,0 +1,575 @@
+use crate::{
+    read_population_points, read_stations, PopulationPoint, PpColumns, Station,
+    StationColumns,
+};
+use memmap2::Mmap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use xxhash_rust::xxh3::Xxh3;
+
+/// The first bytes of every cache file
+const MAGIC: &[u8; 8] = b"ATUPPCOL";
+const VERSION: u32 = 2;
+/// Where the stamp of the source is in the header, after the magic and the
+/// version
+const STAMP_OFFSET: u64 = 12;
+
+/// The binary columnar cache of a csv: a header with the CRS, the size,
+/// modification time and hash of the csv and the names of the columns, then
+/// every column as a contiguous array of little endian f64, aligned to 8
+/// bytes so that it can be used straight from the memory map. Station files
+/// also have a string column with the names at the end.
+pub struct ColumnarFile {
+    mmap: Mmap,
+    path: PathBuf,
+    pub crs: String,
+    pub source_stamp: Stamp,
+    pub source_hash: u64,
+    pub columns: Vec<String>,
+    /// The name of the string column, if there is one
+    pub string_column: Option<String>,
+    pub n_rows: usize,
+    /// Where the f64 columns start
+    data_offset: usize,
+}
+
+/// The cache of a csv sits next to it: a.csv -> a.bin
+pub fn cache_path(csv_path: &str) -> PathBuf {
+    Path::new(csv_path).with_extension("bin")
+}
+
+/// The size and modification time of a file in nanoseconds since the epoch,
+/// which tell that it is unchanged without reading it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stamp {
+    pub size: u64,
+    pub modified: u64,
+}
+
+impl Stamp {
+    pub fn of(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let meta = fs::metadata(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let modified = meta
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Ok(Self {
+            size: meta.len(),
+            modified,
+        })
+    }
+}
+
+/// Hash the whole source file, so that the cache can tell if it is stale
+pub fn hash_file(path: &str) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.digest())
+}
+
+fn write_string(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    out.write_all(&(s.len() as u32).to_le_bytes())?;
+    out.write_all(s.as_bytes())
+}
+
+/// Write the header, the f64 columns, then the optional string column
+fn write_columnar(
+    path: &Path,
+    crs: &str,
+    (source_stamp, source_hash): (Stamp, u64),
+    columns: &[(&str, Vec<f64>)],
+    strings: Option<(&str, &[String])>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let n_rows = columns.first().map_or(0, |(_, c)| c.len());
+
+    let mut header = Vec::new();
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&source_stamp.size.to_le_bytes());
+    header.extend_from_slice(&source_stamp.modified.to_le_bytes());
+    header.extend_from_slice(&source_hash.to_le_bytes());
+    header.extend_from_slice(&(n_rows as u64).to_le_bytes());
+    write_string(&mut header, crs)?;
+    header.extend_from_slice(&(columns.len() as u32).to_le_bytes());
+    for (name, _) in columns {
+        write_string(&mut header, name)?;
+    }
+    header.push(strings.is_some() as u8);
+    if let Some((name, _)) = strings {
+        write_string(&mut header, name)?;
+    }
+    while header.len() % 8 != 0 {
+        header.push(0);
+    }
+
+    let file = File::create(path)
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
+    let mut out = BufWriter::new(file);
+    out.write_all(&header)?;
+    for (_, column) in columns {
+        for value in column {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+    for s in strings.map_or(&[][..], |(_, strings)| strings) {
+        write_string(&mut out, s)?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Reads the header as it walks through the bytes
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let end = self.pos.checked_add(n).ok_or("the cache is truncated")?;
+        let slice = self
+            .bytes
+            .get(self.pos..end)
+            .ok_or("the cache is truncated")?;
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let len = self.u32()? as usize;
+        String::from_utf8(self.take(len)?.to_vec()).map_err(|e| e.to_string())
+    }
+}
+
+impl ColumnarFile {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        // SAFETY: the cache is only written by the cache command, and is
+        // not expected to change while an analysis is running
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let mut cursor = Cursor {
+            bytes: &mmap,
+            pos: 0,
+        };
+        if cursor.take(MAGIC.len())? != MAGIC {
+            return Err(format!("{} is not a cache", path.display()).into());
+        }
+        let version = cursor.u32()?;
+        if version != VERSION {
+            return Err(format!(
+                "{} is version {} of the cache, expected {}",
+                path.display(),
+                version,
+                VERSION
+            )
+            .into());
+        }
+        let source_stamp = Stamp {
+            size: cursor.u64()?,
+            modified: cursor.u64()?,
+        };
+        let source_hash = cursor.u64()?;
+        let n_rows = cursor.u64()? as usize;
+        let crs = cursor.string()?;
+        let n_columns = cursor.u32()?;
+        let columns = (0..n_columns)
+            .map(|_| cursor.string())
+            .collect::<Result<Vec<_>, _>>()?;
+        let string_column = match cursor.take(1)? {
+            [0] => None,
+            _ => Some(cursor.string()?),
+        };
+        let data_offset = cursor.pos.next_multiple_of(8);
+
+        // n_rows comes from the file, so a corrupt one can overflow
+        let data_end = columns
+            .len()
+            .checked_mul(n_rows)
+            .and_then(|n| n.checked_mul(8))
+            .and_then(|len| len.checked_add(data_offset));
+        if data_end.is_none_or(|end| end > mmap.len()) {
+            return Err(format!("{} is truncated", path.display()).into());
+        }
+
+        Ok(Self {
+            crs,
+            source_stamp,
+            source_hash,
+            columns,
+            string_column,
+            n_rows,
+            data_offset,
+            mmap,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The f64 values of the column with the given name
+    pub fn column(
+        &self,
+        name: &str,
+    ) -> Result<impl Iterator<Item = f64> + '_, String> {
+        let idx = self
+            .columns
+            .iter()
+            .position(|c| c == name)
+            .ok_or_else(|| format!("missing column `{}`", name))?;
+        let start = self.data_offset + idx * self.n_rows * 8;
+        let bytes = &self.mmap[start..start + self.n_rows * 8];
+        Ok(bytes
+            .chunks_exact(8)
+            .map(|b| f64::from_le_bytes(b.try_into().unwrap())))
+    }
+
+    /// The string column after the f64 columns
+    pub fn strings(&self) -> Result<Vec<String>, String> {
+        let mut cursor = Cursor {
+            bytes: &self.mmap,
+            pos: self.data_offset + self.columns.len() * self.n_rows * 8,
+        };
+        (0..self.n_rows).map(|_| cursor.string()).collect()
+    }
+
+    /// Whether the cache was made from the source in the given CRS and
+    /// with the given columns. The source is only hashed if its size or
+    /// modification time changed, such as when it was copied. If the hash
+    /// still matches, the new stamp is recorded so that it isn't hashed again
+    fn is_fresh(
+        &self,
+        csv_path: &str,
+        crs: &str,
+        columns: &[&str],
+        string_column: Option<&str>,
+    ) -> Result<bool, Box<dyn std::error::Error>> {
+        if self.crs != crs
+            || !self.columns.iter().eq(columns)
+            || self.string_column.as_deref() != string_column
+        {
+            return Ok(false);
+        }
+        let stamp = Stamp::of(csv_path)?;
+        if self.source_stamp == stamp {
+            return Ok(true);
+        }
+        if self.source_hash != hash_file(csv_path)? {
+            return Ok(false);
+        }
+        if let Err(e) = write_stamp(&self.path, stamp) {
+            eprintln!(
+                "cannot update {}: {}, rerun atupp cache to stop hashing {}",
+                self.path.display(),
+                e,
+                csv_path
+            );
+        }
+        Ok(true)
+    }
+}
+
+/// Record the new stamp of a source whose contents didn't change
+fn write_stamp(path: &Path, stamp: Stamp) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    file.seek(SeekFrom::Start(STAMP_OFFSET))?;
+    file.write_all(&stamp.size.to_le_bytes())?;
+    file.write_all(&stamp.modified.to_le_bytes())
+}
+
+fn pp_column_names(columns: &PpColumns) -> [&str; 5] {
+    [&columns.lon, &columns.lat, &columns.pop, "x", "y"]
+}
+
+/// The weight column is last, if there is one
+fn station_column_names(columns: &StationColumns) -> Vec<&str> {
+    let mut names = vec![columns.lon.as_str(), &columns.lat, "x", "y"];
+    names.extend(columns.weight.as_deref());
+    names
+}
+
+/// Convert the population points in meters into a cache next to them
+pub fn cache_population_points(
+    csv_path: &str,
+    columns: &PpColumns,
+    crs: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let source = (Stamp::of(csv_path)?, hash_file(csv_path)?);
+    let points = read_population_points(File::open(csv_path)?, columns)
+        .map_err(|e| format!("{}: {}", csv_path, e))?;
+
+    let values: [fn(&PopulationPoint) -> f64; 5] =
+        [|p| p.lon, |p| p.lat, |p| p.pop, |p| p.x, |p| p.y];
+    let data: Vec<_> = pp_column_names(columns)
+        .into_iter()
+        .zip(values)
+        .map(|(name, value)| (name, points.iter().map(value).collect()))
+        .collect();
+
+    let path = cache_path(csv_path);
+    write_columnar(&path, crs, source, &data, None)?;
+    Ok(path)
+}
+
+/// Convert the stations in meters into a cache next to them
+pub fn cache_stations(
+    csv_path: &str,
+    columns: &StationColumns,
+    crs: &str,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let source = (Stamp::of(csv_path)?, hash_file(csv_path)?);
+    let stations = read_stations(File::open(csv_path)?, columns)
+        .map_err(|e| format!("{}: {}", csv_path, e))?;
+
+    let values: [fn(&Station) -> f64; 5] =
+        [|s| s.lon, |s| s.lat, |s| s.x, |s| s.y, |s| s.weight];
+    let data: Vec<_> = station_column_names(columns)
+        .into_iter()
+        .zip(values)
+        .map(|(name, value)| (name, stations.iter().map(value).collect()))
+        .collect();
+    let names: Vec<_> = stations.into_iter().map(|s| s.name).collect();
+
+    let path = cache_path(csv_path);
+    let strings = (columns.name.as_str(), &names[..]);
+    write_columnar(&path, crs, source, &data, Some(strings))?;
+    Ok(path)
+}
+
+/// Open the cache of a csv if there is one and it is fresh
+fn open_fresh(
+    csv_path: &str,
+    crs: &str,
+    columns: &[&str],
+    string_column: Option<&str>,
+) -> Result<Option<ColumnarFile>, Box<dyn std::error::Error>> {
+    let path = cache_path(csv_path);
+    if !path.exists() || !Path::new(csv_path).exists() {
+        return Ok(None);
+    }
+    // a cache that can't be read, such as one in an older version of the
+    // format, is stale too
+    let file = match ColumnarFile::open(&path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("{}, reading the csv instead", e);
+            return Ok(None);
+        }
+    };
+    if !file.is_fresh(csv_path, crs, columns, string_column)? {
+        eprintln!("{} is stale, reading the csv instead", path.display());
+        return Ok(None);
+    }
+    Ok(Some(file))
+}
+
+/// Read the population points from the cache if it is fresh, otherwise from
+/// the csv
+pub fn load_population_points_cached(
+    csv_path: &str,
+    columns: &PpColumns,
+    crs: &str,
+) -> Result<Vec<PopulationPoint>, Box<dyn std::error::Error>> {
+    let names = pp_column_names(columns);
+    let Some(file) = open_fresh(csv_path, crs, &names, None)? else {
+        return crate::load_population_points(csv_path, columns);
+    };
+
+    let [lon, lat, pop, x, y] = names.map(|name| file.column(name));
+    let points = lon?
+        .zip(lat?)
+        .zip(pop?)
+        .zip(x?.zip(y?))
+        .map(|(((lon, lat), pop), (x, y))| PopulationPoint {
+            lon,
+            lat,
+            pop,
+            x,
+            y,
+        })
+        .collect();
+    Ok(points)
+}
+
+/// Read the stations from the cache if it is fresh, otherwise from the csv
+pub fn load_stations_cached(
+    csv_path: &str,
+    columns: &StationColumns,
+    crs: &str,
+) -> Result<Vec<Station>, Box<dyn std::error::Error>> {
+    let names = station_column_names(columns);
+    let Some(file) = open_fresh(csv_path, crs, &names, Some(&columns.name))?
+    else {
+        return crate::load_stations(csv_path, columns);
+    };
+
+    let column = |i: usize| file.column(names[i]);
+    let weights: Box<dyn Iterator<Item = f64>> = match names.get(4) {
+        Some(weight) => Box::new(file.column(weight)?),
+        None => Box::new(std::iter::repeat(1.0)),
+    };
+    let stations = file
+        .strings()?
+        .into_iter()
+        .zip(column(0)?.zip(column(1)?))
+        .zip(column(2)?.zip(column(3)?))
+        .zip(weights)
+        .map(|(((name, (lon, lat)), (x, y)), weight)| Station {
+            name,
+            lat,
+            lon,
+            x,
+            y,
+            weight,
+        })
+        .collect();
+    Ok(stations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    fn temp_csv(name: &str, contents: &str) -> String {
+        let dir = std::env::temp_dir().join("atupp_cache_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_population_points_round_trip() {
+        let csv = temp_csv(
+            "pp.csv",
+            "longitude,latitude,population,x,y\n1.5,2.5,3.5,4.5,5.5\n6,7,8,9,10\n",
+        );
+        let columns = PpColumns::default();
+        cache_population_points(&csv, &columns, "EPSG:3857").unwrap();
+
+        let file = ColumnarFile::open(&cache_path(&csv)).unwrap();
+        assert_eq!(file.n_rows, 2);
+        assert_eq!(file.crs, "EPSG:3857");
+        let pops: Vec<_> = file.column("population").unwrap().collect();
+        assert_eq!(pops, [3.5, 8.0]);
+
+        let cached =
+            load_population_points_cached(&csv, &columns, "EPSG:3857").unwrap();
+        let parsed = crate::load_population_points(&csv, &columns).unwrap();
+        assert_eq!(cached, parsed);
+    }
+
+    #[test]
+    fn test_stations_round_trip() {
+        let csv = temp_csv(
+            "stations.csv",
+            "station_name,lat,lon,x,y\n\"Bank, 1\",51.5,-0.08,1,2\nOval,51.4,-0.1,3,4\n",
+        );
+        let columns = StationColumns::default();
+        cache_stations(&csv, &columns, "EPSG:3857").unwrap();
+
+        let cached = load_stations_cached(&csv, &columns, "EPSG:3857").unwrap();
+        let parsed = crate::load_stations(&csv, &columns).unwrap();
+        assert_eq!(cached, parsed);
+    }
+
+    #[test]
+    fn test_station_weights_round_trip() {
+        let csv = temp_csv(
+            "weighted_stations.csv",
+            "station_name,lat,lon,x,y,lines\nBank,51.5,-0.08,1,2,5\n",
+        );
+        let columns = StationColumns {
+            weight: Some("lines".into()),
+            ..StationColumns::default()
+        };
+        cache_stations(&csv, &columns, "EPSG:3857").unwrap();
+        let cached = load_stations_cached(&csv, &columns, "EPSG:3857").unwrap();
+        assert_eq!(cached[0].weight, 5.0);
+
+        // a cache without the weights is stale for them
+        let unweighted = StationColumns::default();
+        cache_stations(&csv, &unweighted, "EPSG:3857").unwrap();
+        let file = ColumnarFile::open(&cache_path(&csv)).unwrap();
+        let names = station_column_names(&columns);
+        let fresh = |file: &ColumnarFile, names: &[&str], name| {
+            file.is_fresh(&csv, "EPSG:3857", names, Some(name)).unwrap()
+        };
+        assert!(!fresh(&file, &names, "station_name"));
+
+        // and so is a cache with the names from another column
+        let names = station_column_names(&unweighted);
+        assert!(fresh(&file, &names, "station_name"));
+        assert!(!fresh(&file, &names, "name"));
+    }
+
+    #[test]
+    fn test_corrupt_row_count() {
+        let csv = temp_csv(
+            "corrupt.csv",
+            "longitude,latitude,population,x,y\n1,2,3,4,5\n",
+        );
+        cache_population_points(&csv, &PpColumns::default(), "EPSG:3857")
+            .unwrap();
+        // n_rows is after the magic, the version, the stamp and the hash
+        let path = cache_path(&csv);
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[36..44].copy_from_slice(&u64::MAX.to_le_bytes());
+        fs::write(&path, bytes).unwrap();
+        let e = ColumnarFile::open(&path).err().unwrap();
+        assert!(e.to_string().ends_with("is truncated"), "{}", e);
+    }
+
+    #[test]
+    fn test_stale_cache() {
+        let csv = temp_csv(
+            "stale.csv",
+            "longitude,latitude,population,x,y\n1,2,3,4,5\n",
+        );
+        let columns = PpColumns::default();
+        cache_population_points(&csv, &columns, "EPSG:3857").unwrap();
+        let file = ColumnarFile::open(&cache_path(&csv)).unwrap();
+        let names = pp_column_names(&columns);
+        let fresh = |crs| file.is_fresh(&csv, crs, &names, None).unwrap();
+        assert!(fresh("EPSG:3857"));
+        assert!(!fresh("EPSG:27700"));
+
+        // rewriting the same contents only changes the modification time,
+        // and the hash still matches
+        fs::write(&csv, "longitude,latitude,population,x,y\n1,2,3,4,5\n")
+            .unwrap();
+        assert!(fresh("EPSG:3857"));
+        // and the new stamp is recorded
+        let reopened = ColumnarFile::open(&cache_path(&csv)).unwrap();
+        assert_eq!(reopened.source_stamp, Stamp::of(&csv).unwrap());
+
+        fs::write(&csv, "longitude,latitude,population,x,y\n1,2,30,4,5\n")
+            .unwrap();
+        assert!(!fresh("EPSG:3857"));
+
+        // so the csv is read instead
+        let points =
+            load_population_points_cached(&csv, &columns, "EPSG:3857").unwrap();
+        assert_eq!(points[0].pop, 30.0);
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{save_point_layer, PointRow, PopulationPoint, Station};
+
+/// The population that depends on a station
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catchment {
+    /// The row of the station in the stations file, from 0
+    pub id: usize,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// The population within the distance, counting the points near
+    /// several stations once for every station
+    pub pop_within: f64,
+    /// The population within the distance, splitting the points near
+    /// several stations evenly between them
+    pub pop_within_split: f64,
+    /// The population of the points this station is the nearest to, its
+    /// Voronoi cell
+    pub pop_nearest: f64,
+}
+
+/// The catchment of every station, from the searches of every point:
+/// within are the IDs (and distances) of the stations within the distance
+/// of every point, nearest the ID of its nearest station. Sorted by
+/// pop_nearest, largest first, then by ID
+pub fn catchments(
+    stations: &[Station],
+    points: &[PopulationPoint],
+    within: &[Vec<(usize, f64)>],
+    nearest: &[Option<usize>],
+) -> Vec<Catchment> {
+    let mut catchments: Vec<_> = stations
+        .iter()
+        .enumerate()
+        .map(|(id, s)| Catchment {
+            id,
+            name: s.name.clone(),
+            lat: s.lat,
+            lon: s.lon,
+            pop_within: 0.0,
+            pop_within_split: 0.0,
+            pop_nearest: 0.0,
+        })
+        .collect();
+
+    for ((p, within), nearest) in points.iter().zip(within).zip(nearest) {
+        let share = p.pop / within.len() as f64;
+        for (id, _) in within {
+            catchments[*id].pop_within += p.pop;
+            catchments[*id].pop_within_split += share;
+        }
+        if let Some(id) = nearest {
+            catchments[*id].pop_nearest += p.pop;
+        }
+    }
+
+    catchments.sort_by(|a, b| {
+        b.pop_nearest
+            .total_cmp(&a.pop_nearest)
+            .then(a.id.cmp(&b.id))
+    });
+    catchments
+}
+
+const COLUMNS: [&str; 7] = [
+    "id",
+    "name",
+    "lat",
+    "lon",
+    "pop_within",
+    "pop_within_split",
+    "pop_nearest",
+];
+
+/// A row of the point layer for every station, in the order of COLUMNS
+fn catchment_rows(catchments: &[Catchment]) -> Vec<PointRow> {
+    catchments
+        .iter()
+        .map(|c| PointRow {
+            lon: c.lon,
+            lat: c.lat,
+            values: vec![
+                c.id.into(),
+                c.name.as_str().into(),
+                c.lat.into(),
+                c.lon.into(),
+                c.pop_within.into(),
+                c.pop_within_split.into(),
+                c.pop_nearest.into(),
+            ],
+        })
+        .collect()
+}
+
+/// Write the catchments as a csv to path, and as GeoJSON next to it with
+/// the .geojson extension. Returns the path of the GeoJSON
+pub fn save_catchments(
+    path: &str,
+    catchments: &[Catchment],
+) -> Result<String, Box<dyn std::error::Error>> {
+    save_point_layer(path, &COLUMNS, &catchment_rows(catchments))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::point_layer_geojson;
+
+    fn station(name: &str) -> Station {
+        Station {
+            name: name.into(),
+            lat: 51.5,
+            lon: -0.1,
+            x: 0.0,
+            y: 0.0,
+            weight: 1.0,
+        }
+    }
+
+    fn point(pop: f64) -> PopulationPoint {
+        PopulationPoint {
+            lon: 0.0,
+            lat: 0.0,
+            pop,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_catchments() {
+        let stations = [station("Bank"), station("Monument"), station("Oval")];
+        let points = [point(10.0), point(4.0), point(1.0)];
+        let within = [vec![(0, 10.0), (1, 20.0)], vec![(1, 0.0)], vec![]];
+        let nearest = [Some(0), Some(1), None];
+        let c = catchments(&stations, &points, &within, &nearest);
+
+        let names: Vec<_> = c.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, ["Bank", "Monument", "Oval"]);
+        assert_eq!(c[0].pop_within, 10.0);
+        assert_eq!(c[0].pop_within_split, 5.0);
+        assert_eq!(c[1].pop_within, 14.0);
+        assert_eq!(c[1].pop_within_split, 9.0);
+        assert_eq!(c[1].pop_nearest, 4.0);
+        assert_eq!(c[2].pop_within, 0.0);
+        // the split population adds up to the population near any station
+        let split: f64 = c.iter().map(|c| c.pop_within_split).sum();
+        assert_eq!(split, 14.0);
+    }
+
+    #[test]
+    fn test_geojson_properties() {
+        let c = catchments(
+            &[station("Bank")],
+            &[point(2.0)],
+            &[vec![(0, 0.0)]],
+            &[Some(0)],
+        );
+        let geojson = point_layer_geojson(&COLUMNS, &catchment_rows(&c));
+        let properties = geojson.features[0].properties.as_ref().unwrap();
+        assert_eq!(properties["name"], "Bank");
+        assert_eq!(properties["pop_nearest"], 2.0);
+    }
+}
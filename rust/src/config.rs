@@ -0,0 +1,186 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+
+/// Where the city registry is read from, relative to the rust directory
+/// like the data paths inside it
+pub const CITIES_PATH: &str = "cities.toml";
+
+/// Every city that can be analysed, keyed by the name given on the
+/// command line
+#[derive(Debug, Deserialize)]
+#[serde(transparent)]
+pub struct Cities(BTreeMap<String, City>);
+
+/// The files and file layouts of a single city
+#[derive(Debug, Clone, Deserialize)]
+pub struct City {
+    /// The city boundaries as a GeoJSON in WGS84, EPSG:4326
+    pub boundaries: String,
+    /// The national population points, as downloaded
+    pub national_pp: String,
+    /// The population points clipped to the city boundaries
+    pub pp: String,
+    /// The clipped population points with x and y columns in meters
+    pub pp_meters: String,
+    /// The station coordinates in lat/lon
+    pub stations: String,
+    /// The station coordinates with x and y columns in meters
+    pub stations_meters: String,
+    /// A local OSM extract (.osm.pbf or .osm) covering the city, for the
+    /// walking network
+    pub osm: Option<String>,
+    /// A GTFS feed in a zip, to read the stations and their services from
+    pub gtfs: Option<String>,
+    /// The projected CRS of the files in meters
+    #[serde(default = "default_crs")]
+    pub crs: String,
+    /// The properties of the boundary features that name the regions, for
+    /// the per-region analyses
+    pub region_properties: Option<RegionProperties>,
+    #[serde(default)]
+    pub pp_columns: PpColumns,
+    #[serde(default)]
+    pub station_columns: StationColumns,
+}
+
+fn default_crs() -> String {
+    "EPSG:3857".into()
+}
+
+/// The boundary feature properties with the ID and the name of a region
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegionProperties {
+    pub id: String,
+    pub name: String,
+}
+
+/// Header names of the population point files. The files in meters
+/// also have x and y columns
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PpColumns {
+    pub lon: String,
+    pub lat: String,
+    pub pop: String,
+}
+
+impl Default for PpColumns {
+    fn default() -> Self {
+        Self {
+            lon: "longitude".into(),
+            lat: "latitude".into(),
+            pop: "population".into(),
+        }
+    }
+}
+
+/// Header names of the station files. The files in meters also have x and
+/// y columns
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct StationColumns {
+    pub name: String,
+    pub lat: String,
+    pub lon: String,
+    /// The service weight of every station, such as departures_per_hour
+    /// or lines from the gtfs command. Without it every station weighs 1
+    pub weight: Option<String>,
+}
+
+impl Default for StationColumns {
+    fn default() -> Self {
+        Self {
+            name: "station_name".into(),
+            lat: "lat".into(),
+            lon: "lon".into(),
+            weight: None,
+        }
+    }
+}
+
+impl Cities {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let s = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Self::parse(&s).map_err(|e| format!("{}: {}", path, e).into())
+    }
+
+    pub fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn get(&self, name: &str) -> Result<&City, Box<dyn std::error::Error>> {
+        self.0.get(name).ok_or_else(|| {
+            format!(
+                "unknown city `{}`, expected one of: {}",
+                name,
+                self.names().collect::<Vec<_>>().join(", ")
+            )
+            .into()
+        })
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(|k| k.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const CITIES: &str = r#"
+        [osaka]
+        boundaries = "osaka.geojson"
+        national_pp = "jpn.csv"
+        pp = "osaka_pp.csv"
+        pp_meters = "osaka_pp_meters.csv"
+        stations = "osaka_stations.csv"
+        stations_meters = "osaka_stations_meters.csv"
+
+        [paris]
+        boundaries = "paris.geojson"
+        national_pp = "fra.csv"
+        pp = "paris_pp.csv"
+        pp_meters = "paris_pp_meters.csv"
+        stations = "paris_stations.csv"
+        stations_meters = "paris_stations_meters.csv"
+        osm = "ile-de-france.osm.pbf"
+        crs = "EPSG:2154"
+        pp_columns = { lat = "Lat", lon = "Lon", pop = "Population" }
+        region_properties = { id = "code_insee", name = "nom" }
+    "#;
+
+    #[test]
+    fn test_parse_cities() {
+        let cities = Cities::parse(CITIES).unwrap();
+        assert_eq!(cities.names().collect::<Vec<_>>(), ["osaka", "paris"]);
+
+        let osaka = cities.get("osaka").unwrap();
+        assert_eq!(osaka.pp_columns.lat, "latitude");
+        assert_eq!(osaka.crs, "EPSG:3857");
+
+        let paris = cities.get("paris").unwrap();
+        assert_eq!(paris.pp_columns.pop, "Population");
+        assert_eq!(paris.crs, "EPSG:2154");
+        assert_eq!(paris.region_properties.as_ref().unwrap().name, "nom");
+        assert!(osaka.region_properties.is_none());
+        assert_eq!(paris.osm.as_deref(), Some("ile-de-france.osm.pbf"));
+        assert!(osaka.osm.is_none());
+        assert_eq!(paris.station_columns.name, "station_name");
+    }
+
+    #[test]
+    fn test_unknown_city() {
+        let cities = Cities::parse(CITIES).unwrap();
+        let err = cities.get("tokyo").unwrap_err().to_string();
+        assert_eq!(err, "unknown city `tokyo`, expected one of: osaka, paris");
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let s = "[osaka]\nboundaries = \"osaka.geojson\"";
+        assert!(Cities::parse(s).is_err());
+    }
+}
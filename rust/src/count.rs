@@ -0,0 +1,200 @@
+use crate::{DistanceModel, PopulationPoint, StationTree};
+use rayon::prelude::*;
+use std::ops::AddAssign;
+
+/// The number of stations within every threshold of every population point.
+/// The result is indexed by threshold, then by point, in the given orders.
+///
+/// The counts nest, so the tree is only searched once per point at the
+/// largest threshold, and every station found is bucketed by its distance
+pub fn count_within_thresholds(
+    tree: &StationTree,
+    points: &[PopulationPoint],
+    thresholds: &[f64],
+    model: DistanceModel,
+) -> Vec<Vec<u32>> {
+    sum_nested(points, thresholds, |p, max_distance| {
+        model
+            .locate_with_distance(tree, *p, max_distance)
+            .map(|(_, d)| (d, 1))
+    })
+}
+
+/// The sum of the weights of the stations within every threshold of every
+/// population point, indexed like count_within_thresholds. With the
+/// default weight of 1, these are the counts
+pub fn sum_weights_within_thresholds(
+    tree: &StationTree,
+    points: &[PopulationPoint],
+    thresholds: &[f64],
+    model: DistanceModel,
+) -> Vec<Vec<f64>> {
+    sum_nested(points, thresholds, |p, max_distance| {
+        model
+            .locate_with_distance(tree, *p, max_distance)
+            .map(|(station, d)| (d, station.data.weight))
+    })
+}
+
+/// The IDs of the stations within max_distance of every population point,
+/// with their ground distances, in the same order as the points
+pub fn stations_within(
+    tree: &StationTree,
+    points: &[PopulationPoint],
+    max_distance: f64,
+    model: DistanceModel,
+) -> Vec<Vec<(usize, f64)>> {
+    points
+        .par_iter()
+        .map(|p| {
+            model
+                .locate_with_distance(tree, *p, max_distance)
+                .map(|(station, d)| (station.data.id, d))
+                .collect()
+        })
+        .collect()
+}
+
+/// Bucket the values of the stations of every item by their distance to
+/// it, and sum them within every threshold, indexed by threshold then by
+/// item. stations gives the distance and value of every station within the
+/// largest threshold of an item
+pub(crate) fn sum_nested<T, I, V>(
+    items: &[T],
+    thresholds: &[f64],
+    stations: impl Fn(&T, f64) -> I + Sync,
+) -> Vec<Vec<V>>
+where
+    T: Sync,
+    I: Iterator<Item = (f64, V)>,
+    V: Copy + Default + AddAssign + Send + Sync,
+{
+    let mut sorted = thresholds.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let Some(&max_distance) = sorted.last() else {
+        return vec![];
+    };
+    // the position of every threshold in the sorted thresholds
+    let ranks: Vec<_> = thresholds
+        .iter()
+        .map(|t| sorted.partition_point(|s| s < t))
+        .collect();
+
+    let per_item: Vec<_> = items
+        .par_iter()
+        .map(|item| {
+            let mut buckets = vec![V::default(); sorted.len()];
+            for (d, value) in stations(item, max_distance) {
+                if d <= max_distance {
+                    buckets[sorted.partition_point(|t| *t < d)] += value;
+                }
+            }
+            // stations within a threshold are also within the larger ones
+            for i in 1..buckets.len() {
+                let previous = buckets[i - 1];
+                buckets[i] += previous;
+            }
+            buckets
+        })
+        .collect();
+
+    ranks
+        .iter()
+        .map(|rank| per_item.iter().map(|counts| counts[*rank]).collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{StationPoint, StationRecord};
+
+    fn point(x: f64, y: f64) -> PopulationPoint {
+        PopulationPoint {
+            lon: 0.0,
+            lat: 0.0,
+            pop: 1.0,
+            x,
+            y,
+        }
+    }
+
+    fn station(id: usize, x: f64, y: f64, weight: f64) -> StationPoint {
+        let record = StationRecord {
+            id,
+            name: String::new(),
+            weight,
+        };
+        StationPoint::new((x, y), record)
+    }
+
+    fn tree(stations: &[(f64, f64)], weight: f64) -> StationTree {
+        StationTree::bulk_load(
+            stations
+                .iter()
+                .enumerate()
+                .map(|(id, (x, y))| station(id, *x, *y, weight))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_count_within_thresholds() {
+        let tree = tree(&[(0.0, 100.0), (0.0, 250.0), (0.0, 900.0)], 1.0);
+        let points = [point(0.0, 0.0), point(0.0, 1000.0)];
+        let counts = count_within_thresholds(
+            &tree,
+            &points,
+            &[300.0, 100.0, 2000.0],
+            DistanceModel::Planar,
+        );
+        assert_eq!(counts, [vec![2, 1], vec![1, 1], vec![3, 3]]);
+
+        let mut within =
+            stations_within(&tree, &points, 300.0, DistanceModel::Planar);
+        within[0].sort_by_key(|(id, _)| *id);
+        assert_eq!(within, [vec![(0, 100.0), (1, 250.0)], vec![(2, 100.0)]]);
+    }
+
+    #[test]
+    fn test_same_as_separate_searches() {
+        let stations: Vec<_> = (0..50)
+            .map(|i| ((i * 37 % 101) as f64 * 20.0, i as f64 * 30.0))
+            .collect();
+        let tree = tree(&stations, 1.0);
+        let points: Vec<_> = (0..40)
+            .map(|i| point(i as f64 * 50.0, (i * 13 % 17) as f64 * 90.0))
+            .collect();
+        let thresholds = [100.0, 250.0, 500.0, 1000.0];
+        let counts = count_within_thresholds(
+            &tree,
+            &points,
+            &thresholds,
+            DistanceModel::Planar,
+        );
+        for (t, counts) in thresholds.iter().zip(counts) {
+            for (p, count) in points.iter().zip(counts) {
+                let expected =
+                    tree.locate_within_distance((p.x, p.y), t * t).count();
+                assert_eq!(count as usize, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sum_weights_within_thresholds() {
+        let tree = StationTree::bulk_load(vec![
+            station(0, 0.0, 100.0, 2.0),
+            station(1, 0.0, 250.0, 10.5),
+            station(2, 0.0, 900.0, 0.0),
+        ]);
+        let points = [point(0.0, 0.0), point(0.0, 1000.0)];
+        let sums = sum_weights_within_thresholds(
+            &tree,
+            &points,
+            &[300.0, 100.0],
+            DistanceModel::Planar,
+        );
+        assert_eq!(sums, [vec![12.5, 0.0], vec![2.0, 0.0]]);
+    }
+}
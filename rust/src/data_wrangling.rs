@@ -1,32 +1,424 @@
-use std::fs;
-
-// TODO: use serde
-pub fn parse_csv_line(line: &str) -> Vec<&str> {
-    line.split(',')
-        .map(|x| {
-            let a = x.strip_prefix('"').unwrap_or(x);
-            let b = a
-                .strip_suffix('"')
-                .unwrap_or_else(|| a.strip_suffix("\"\n").unwrap_or(a));
-            b
+use crate::{PpColumns, Region, StationColumns, REGION_ID, REGION_NAME};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+
+/// A row of the population point file in meters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PopulationPoint {
+    pub lon: f64,
+    pub lat: f64,
+    pub pop: f64,
+    pub x: f64,
+    pub y: f64,
+}
+
+/// A row of the station file in meters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Station {
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    pub x: f64,
+    pub y: f64,
+    /// How much service the station has, 1 without a weight column
+    pub weight: f64,
+}
+
+/// The region of every population point, as tagged by clip --regions
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointRegions {
+    /// Every distinct region, sorted by name
+    pub regions: Vec<Region>,
+    /// The position in regions of the region of every point
+    pub of_point: Vec<usize>,
+}
+
+/// Find the position of a column by its header name
+pub fn column_index(
+    headers: &StringRecord,
+    name: &str,
+) -> Result<usize, String> {
+    headers
+        .iter()
+        .position(|header| header == name)
+        .ok_or_else(|| format!("missing column `{}`", name))
+}
+
+/// Parse a field as a number, reporting the line if it is malformed
+pub fn parse_field(
+    record: &StringRecord,
+    idx: usize,
+    column: &str,
+) -> Result<f64, String> {
+    let line = record.position().map_or(0, |pos| pos.line());
+    let field = record.get(idx).ok_or_else(|| {
+        format!("line {}: missing value for column `{}`", line, column)
+    })?;
+    field.trim().parse().map_err(|_| {
+        format!(
+            "line {}: cannot parse `{}` in column `{}` as a number",
+            line, field, column
+        )
+    })
+}
+
+/// A station weight, which must be a finite number that is not negative
+fn parse_weight(
+    record: &StringRecord,
+    idx: usize,
+    column: &str,
+) -> Result<f64, String> {
+    let weight = parse_field(record, idx, column)?;
+    let line = record.position().map_or(0, |pos| pos.line());
+    if !weight.is_finite() {
+        return Err(format!(
+            "line {}: the weight in column `{}` is not a finite number",
+            line, column
+        ));
+    }
+    if weight < 0.0 {
+        return Err(format!(
+            "line {}: the weight in column `{}` is negative",
+            line, column
+        ));
+    }
+    Ok(weight)
+}
+
+pub fn read_population_points<R: Read>(
+    reader: R,
+    columns: &PpColumns,
+) -> Result<Vec<PopulationPoint>, String> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+
+    let names = [&columns.lon, &columns.lat, &columns.pop, "x", "y"];
+    let idxs = names
+        .iter()
+        .map(|name| column_index(&headers, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            let mut values = [0.0; 5];
+            for (i, (idx, name)) in idxs.iter().zip(names).enumerate() {
+                values[i] = parse_field(&record, *idx, name)?;
+            }
+            let [lon, lat, pop, x, y] = values;
+            Ok(PopulationPoint {
+                lon,
+                lat,
+                pop,
+                x,
+                y,
+            })
         })
         .collect()
 }
 
-pub fn load_stations(path: &str) -> Vec<(f64, f64)> {
-    let file = fs::read_to_string(path).unwrap();
-    let lines = file.split('\n');
-    lines
-        .skip(1)
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            let xs = parse_csv_line(line);
+pub fn read_stations<R: Read>(
+    reader: R,
+    columns: &StationColumns,
+) -> Result<Vec<Station>, String> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+
+    let name_idx = column_index(&headers, &columns.name)?;
+    let names = [&columns.lat, &columns.lon, "x", "y"];
+    let idxs = names
+        .iter()
+        .map(|name| column_index(&headers, name))
+        .collect::<Result<Vec<_>, _>>()?;
+    let weight = match &columns.weight {
+        Some(name) => Some((column_index(&headers, name)?, name)),
+        None => None,
+    };
 
-            // both london and tokyo is (name, lat, lon, x, y)
-            let x: f64 = xs[3].parse().unwrap();
-            let y: f64 = xs[4].parse().unwrap();
-            (x, y)
+    reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            let mut values = [0.0; 4];
+            for (i, (idx, name)) in idxs.iter().zip(names).enumerate() {
+                values[i] = parse_field(&record, *idx, name)?;
+            }
+            let [lat, lon, x, y] = values;
+            let name = record.get(name_idx).unwrap_or_default().to_string();
+            let weight = match weight {
+                Some((idx, column)) => parse_weight(&record, idx, column)?,
+                None => 1.0,
+            };
+            Ok(Station {
+                name,
+                lat,
+                lon,
+                x,
+                y,
+                weight,
+            })
         })
         .collect()
 }
 
+pub fn read_point_regions<R: Read>(reader: R) -> Result<PointRegions, String> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+    let id_idx = column_index(&headers, REGION_ID)?;
+    let name_idx = column_index(&headers, REGION_NAME)?;
+
+    let mut positions = HashMap::new();
+    let mut regions = vec![];
+    let mut of_point = vec![];
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        let region = Region {
+            id: record.get(id_idx).unwrap_or_default().to_string(),
+            name: record.get(name_idx).unwrap_or_default().to_string(),
+        };
+        let i = *positions.entry(region.clone()).or_insert_with(|| {
+            regions.push(region);
+            regions.len() - 1
+        });
+        of_point.push(i);
+    }
+
+    // sort by name, and renumber the points to match
+    let mut order: Vec<_> = (0..regions.len()).collect();
+    order.sort_by(|a, b| regions[*a].name.cmp(&regions[*b].name));
+    let mut new_position = vec![0; order.len()];
+    for (new, old) in order.iter().enumerate() {
+        new_position[*old] = new;
+    }
+    Ok(PointRegions {
+        regions: order.into_iter().map(|i| regions[i].clone()).collect(),
+        of_point: of_point.into_iter().map(|i| new_position[i]).collect(),
+    })
+}
+
+/// Read the population points in meters, as written by the reprojection
+pub fn load_population_points(
+    path: &str,
+    columns: &PpColumns,
+) -> Result<Vec<PopulationPoint>, Box<dyn std::error::Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    read_population_points(file, columns)
+        .map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// Read the stations in meters, as written by the reprojection
+pub fn load_stations(
+    path: &str,
+    columns: &StationColumns,
+) -> Result<Vec<Station>, Box<dyn std::error::Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    read_stations(file, columns).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// Write stations in meters, in the layout that read_stations reads. The
+/// weights are only written if there is a weight column
+pub fn write_stations<W: Write>(
+    writer: W,
+    stations: &[Station],
+    columns: &StationColumns,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    let mut headers =
+        vec![columns.name.as_str(), &columns.lat, &columns.lon, "x", "y"];
+    headers.extend(columns.weight.as_deref());
+    writer.write_record(headers)?;
+    for s in stations {
+        let mut record = vec![
+            s.name.clone(),
+            s.lat.to_string(),
+            s.lon.to_string(),
+            s.x.to_string(),
+            s.y.to_string(),
+        ];
+        if columns.weight.is_some() {
+            record.push(s.weight.to_string());
+        }
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn save_stations(
+    path: &str,
+    stations: &[Station],
+    columns: &StationColumns,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("cannot write {}: {}", path, e))?;
+    write_stations(file, stations, columns)
+        .map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// Read the region columns of the population points in meters
+pub fn load_point_regions(
+    path: &str,
+) -> Result<PointRegions, Box<dyn std::error::Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    read_point_regions(file).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_population_points() {
+        let csv = "\
+\"Lat\",\"Lon\",\"Population\",\"x\",\"y\"
+\"51.5781944444857\",\"-0.24125000000019298\",\"9.821008556019821\",1,2
+";
+        let columns = PpColumns {
+            lon: "Lon".into(),
+            lat: "Lat".into(),
+            pop: "Population".into(),
+        };
+        let points = read_population_points(csv.as_bytes(), &columns).unwrap();
+        assert_eq!(
+            points,
+            [PopulationPoint {
+                lon: -0.24125000000019298,
+                lat: 51.5781944444857,
+                pop: 9.821008556019821,
+                x: 1.0,
+                y: 2.0,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_read_stations_quoted_commas() {
+        let csv = "\
+station_name,lat,lon,x,y
+\"Heathrow Terminals 2, 3\",51.47,-0.45,10.5,20.5
+";
+        let stations =
+            read_stations(csv.as_bytes(), &StationColumns::default()).unwrap();
+        assert_eq!(stations[0].name, "Heathrow Terminals 2, 3");
+        assert_eq!(stations[0].x, 10.5);
+        assert_eq!(stations[0].y, 20.5);
+    }
+
+    #[test]
+    fn test_write_stations_round_trip() {
+        let stations = vec![Station {
+            name: "Heathrow Terminals 2, 3".into(),
+            lat: 51.47,
+            lon: -0.45,
+            x: 10.5,
+            y: 20.5,
+            weight: 1.0,
+        }];
+        let columns = StationColumns {
+            name: "name".into(),
+            lat: "lat".into(),
+            lon: "lon".into(),
+            weight: None,
+        };
+        let mut csv = vec![];
+        write_stations(&mut csv, &stations, &columns).unwrap();
+        assert_eq!(read_stations(&csv[..], &columns).unwrap(), stations);
+    }
+
+    #[test]
+    fn test_write_station_weights_round_trip() {
+        let stations = vec![Station {
+            name: "Shinjuku".into(),
+            lat: 35.69,
+            lon: 139.70,
+            x: 1.0,
+            y: 2.0,
+            weight: 120.5,
+        }];
+        let columns = StationColumns {
+            weight: Some("departures_per_hour".into()),
+            ..StationColumns::default()
+        };
+        let mut csv = vec![];
+        write_stations(&mut csv, &stations, &columns).unwrap();
+        assert_eq!(read_stations(&csv[..], &columns).unwrap(), stations);
+    }
+
+    #[test]
+    fn test_read_station_weights() {
+        let csv = "\
+name,lat,lon,x,y,departures_per_hour
+Shinjuku,35.69,139.70,1,2,120.5
+Okutama,35.81,139.10,3,4,1.5
+";
+        let mut columns = StationColumns {
+            name: "name".into(),
+            ..StationColumns::default()
+        };
+        let stations = read_stations(csv.as_bytes(), &columns).unwrap();
+        assert_eq!(stations[0].weight, 1.0);
+
+        columns.weight = Some("departures_per_hour".into());
+        let stations = read_stations(csv.as_bytes(), &columns).unwrap();
+        assert_eq!(stations[0].weight, 120.5);
+        assert_eq!(stations[1].weight, 1.5);
+
+        let csv = "name,lat,lon,x,y,departures_per_hour\na,1,2,3,4,-1\n";
+        let err = read_stations(csv.as_bytes(), &columns).unwrap_err();
+        assert_eq!(
+            err,
+            "line 2: the weight in column `departures_per_hour` is negative"
+        );
+
+        let csv = "name,lat,lon,x,y,departures_per_hour\na,1,2,3,4,NaN\n";
+        let err = read_stations(csv.as_bytes(), &columns).unwrap_err();
+        assert_eq!(
+            err,
+            "line 2: the weight in column `departures_per_hour` is not a \
+             finite number"
+        );
+    }
+
+    #[test]
+    fn test_malformed_row() {
+        let csv = "\
+station_name,lat,lon,x,y
+a,51.47,-0.45,10.5,20.5
+b,51.47,-0.45,oops,20.5
+";
+        let err = read_stations(csv.as_bytes(), &StationColumns::default())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "line 3: cannot parse `oops` in column `x` as a number"
+        );
+    }
+
+    #[test]
+    fn test_missing_column() {
+        let csv = "name,lat,lon,x,y\n";
+        let err = read_stations(csv.as_bytes(), &StationColumns::default())
+            .unwrap_err();
+        assert_eq!(err, "missing column `station_name`");
+    }
+
+    #[test]
+    fn test_read_point_regions() {
+        let csv = "\
+x,y,region_id,region_name
+1,2,E09000030,Tower Hamlets
+3,4,E09000004,Bexley
+5,6,E09000030,Tower Hamlets
+";
+        let regions = read_point_regions(csv.as_bytes()).unwrap();
+        assert_eq!(regions.regions[0].name, "Bexley");
+        assert_eq!(regions.regions[1].id, "E09000030");
+        assert_eq!(regions.of_point, [1, 0, 1]);
+    }
+}
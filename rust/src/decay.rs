@@ -0,0 +1,129 @@
+use std::str::FromStr;
+
+/// How much a station counts for a population point, given the distance
+/// between them: from 1 next to the station, down towards 0 further away
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decay {
+    /// Every distance counts fully
+    Step,
+    /// 1 - d / max, down to 0 at max
+    Linear { max: f64 },
+    /// exp(-d / scale)
+    Exponential { scale: f64 },
+    /// 1 / (1 + exp((d - midpoint) / steepness)), which is 1/2 at the
+    /// midpoint and falls off over about steepness meters either side
+    Logistic { midpoint: f64, steepness: f64 },
+    /// exp(-(d / sigma)^2 / 2)
+    Gaussian { sigma: f64 },
+    /// d^-beta, where distances under a meter count as a meter so that the
+    /// weight is at most 1
+    InversePower { beta: f64 },
+}
+
+impl FromStr for Decay {
+    type Err = String;
+
+    /// step, linear:MAX, exponential:SCALE, logistic:MIDPOINT:STEEPNESS,
+    /// gaussian:SIGMA or power:BETA
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let params = if params.is_empty() {
+            vec![]
+        } else {
+            params
+                .split(':')
+                .map(|x| {
+                    x.trim()
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|x| *x > 0.0)
+                        .ok_or_else(|| {
+                            format!("`{}` in `{}` is not positive", x, s)
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        match (name, &params[..]) {
+            ("step", []) => Ok(Self::Step),
+            ("linear", [max]) => Ok(Self::Linear { max: *max }),
+            ("exponential", [scale]) => Ok(Self::Exponential { scale: *scale }),
+            ("logistic", [midpoint, steepness]) => Ok(Self::Logistic {
+                midpoint: *midpoint,
+                steepness: *steepness,
+            }),
+            ("gaussian", [sigma]) => Ok(Self::Gaussian { sigma: *sigma }),
+            ("power", [beta]) => Ok(Self::InversePower { beta: *beta }),
+            _ => Err(format!(
+                "unknown decay `{}`, expected one of: step, linear:MAX, \
+                 exponential:SCALE, logistic:MIDPOINT:STEEPNESS, \
+                 gaussian:SIGMA, power:BETA",
+                s
+            )),
+        }
+    }
+}
+
+impl Decay {
+    /// The weight of a station at distance d meters
+    pub fn weight(self, d: f64) -> f64 {
+        match self {
+            Self::Step => 1.0,
+            Self::Linear { max } => (1.0 - d / max).max(0.0),
+            Self::Exponential { scale } => (-d / scale).exp(),
+            Self::Logistic {
+                midpoint,
+                steepness,
+            } => 1.0 / (1.0 + ((d - midpoint) / steepness).exp()),
+            Self::Gaussian { sigma } => (-0.5 * (d / sigma).powi(2)).exp(),
+            Self::InversePower { beta } => d.max(1.0).powf(-beta),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_decay() {
+        assert_eq!("step".parse(), Ok(Decay::Step));
+        assert_eq!(
+            "gaussian:500".parse(),
+            Ok(Decay::Gaussian { sigma: 500.0 })
+        );
+        assert_eq!("power:1.5".parse(), Ok(Decay::InversePower { beta: 1.5 }));
+        assert_eq!(
+            "logistic:1000:100".parse(),
+            Ok(Decay::Logistic {
+                midpoint: 1000.0,
+                steepness: 100.0
+            })
+        );
+        assert!("gaussian".parse::<Decay>().is_err());
+        assert!("logistic:1000".parse::<Decay>().is_err());
+        assert!("power:-1".parse::<Decay>().is_err());
+        assert!("cliff".parse::<Decay>().is_err());
+    }
+
+    #[test]
+    fn test_weights() {
+        let gaussian = Decay::Gaussian { sigma: 100.0 };
+        assert_eq!(gaussian.weight(0.0), 1.0);
+        assert!((gaussian.weight(100.0) - 0.6065).abs() < 1e-4);
+        let linear = Decay::Linear { max: 1000.0 };
+        assert_eq!(linear.weight(250.0), 0.75);
+        assert_eq!(linear.weight(2000.0), 0.0);
+        let exponential = Decay::Exponential { scale: 500.0 };
+        assert!((exponential.weight(500.0) - (-1.0_f64).exp()).abs() < 1e-12);
+        let logistic = Decay::Logistic {
+            midpoint: 1000.0,
+            steepness: 100.0,
+        };
+        assert_eq!(logistic.weight(1000.0), 0.5);
+        assert!(logistic.weight(500.0) > 0.99);
+        assert!(logistic.weight(1500.0) < 0.01);
+        let power = Decay::InversePower { beta: 2.0 };
+        assert_eq!(power.weight(0.5), 1.0);
+        assert_eq!(power.weight(10.0), 0.01);
+    }
+}
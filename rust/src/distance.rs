@@ -0,0 +1,301 @@
+use crate::{PopulationPoint, Station};
+use geo::{Distance, Geodesic, Haversine, Point};
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use std::f64::consts::FRAC_PI_2;
+use std::str::FromStr;
+
+/// The radius of the sphere behind Web Mercator, EPSG:3857
+const MERCATOR_RADIUS: f64 = 6_378_137.0;
+
+/// The geodesic and haversine distances differ from the distances on the
+/// Mercator sphere by less than this ratio, which is used to widen the
+/// search radius in the tree so that no station is missed
+const MARGIN: f64 = 1.01;
+
+/// What the tree knows about a station besides its position, so that the
+/// searches can tell which station they found
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationRecord {
+    /// The row of the station in the stations file, from 0
+    pub id: usize,
+    pub name: String,
+    /// How much service the station has, 1 without a weight column
+    pub weight: f64,
+}
+
+/// A station in the tree: its position and its record
+pub type StationPoint = GeomWithData<(f64, f64), StationRecord>;
+
+/// The stations of a city, as every analysis searches them
+pub type StationTree = RTree<StationPoint>;
+
+/// How the distance between a population point and a station is measured.
+/// Every model measures in meters on the ground
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DistanceModel {
+    /// Euclidean distance of x and y. Only ground meters if the files are in
+    /// a local projection, such as EPSG:27700 for London or the JGD2011
+    /// plane rectangular zones for Tokyo
+    Planar,
+    /// Euclidean distance of x and y in EPSG:3857, multiplied by the
+    /// Mercator scale factor at the latitude of the population point
+    Mercator,
+    /// Great circle distance of lat and lon on a sphere
+    Haversine,
+    /// Geodesic distance of lat and lon on the WGS84 ellipsoid
+    #[default]
+    Geodesic,
+}
+
+impl FromStr for DistanceModel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "planar" => Ok(Self::Planar),
+            "mercator" => Ok(Self::Mercator),
+            "haversine" => Ok(Self::Haversine),
+            "geodesic" => Ok(Self::Geodesic),
+            _ => Err(format!(
+                "unknown distance model `{}`, expected one of: \
+                 planar, mercator, haversine, geodesic",
+                s
+            )),
+        }
+    }
+}
+
+impl DistanceModel {
+    /// Whether the tree is built from lat and lon instead of x and y
+    fn on_sphere(self) -> bool {
+        matches!(self, Self::Haversine | Self::Geodesic)
+    }
+
+    /// The position of a station in the tree
+    pub fn station_position(self, s: &Station) -> (f64, f64) {
+        if self.on_sphere() {
+            to_mercator(s.lon, s.lat)
+        } else {
+            (s.x, s.y)
+        }
+    }
+
+    /// Bulk load the stations into a tree, with their row as their ID
+    pub fn station_tree(self, stations: &[Station]) -> StationTree {
+        let points = stations
+            .iter()
+            .enumerate()
+            .map(|(id, s)| {
+                let record = StationRecord {
+                    id,
+                    name: s.name.clone(),
+                    weight: s.weight,
+                };
+                StationPoint::new(self.station_position(s), record)
+            })
+            .collect();
+        RTree::bulk_load(points)
+    }
+
+    /// The position of a population point in the tree
+    pub fn point_position(self, p: &PopulationPoint) -> (f64, f64) {
+        if self.on_sphere() {
+            to_mercator(p.lon, p.lat)
+        } else {
+            (p.x, p.y)
+        }
+    }
+
+    /// The ground distance between a population point and a station in the
+    /// tree, in meters
+    pub fn distance(self, p: &PopulationPoint, station: &(f64, f64)) -> f64 {
+        let (x, y) = self.point_position(p);
+        let d = ((station.0 - x).powi(2) + (station.1 - y).powi(2)).sqrt();
+        let from = Point::new(p.lon, p.lat);
+        match self {
+            Self::Planar => d,
+            Self::Mercator => d * p.lat.to_radians().cos(),
+            Self::Haversine => {
+                Haversine::distance(from, from_mercator(station))
+            }
+            Self::Geodesic => Geodesic::distance(from, from_mercator(station)),
+        }
+    }
+
+    /// The radius in the tree that contains every station within
+    /// max_distance ground meters of the population point
+    fn search_radius(self, p: &PopulationPoint, max_distance: f64) -> f64 {
+        match self {
+            Self::Planar => max_distance,
+            Self::Mercator => max_distance / p.lat.to_radians().cos(),
+            Self::Haversine | Self::Geodesic => {
+                max_distance * MARGIN / max_lat(p.lat, max_distance).cos()
+            }
+        }
+    }
+
+    /// Every station within max_distance ground meters of the population
+    /// point. The tree can also hold anything else positioned like the
+    /// stations, such as the population points themselves
+    pub fn locate_within_distance<T>(
+        self,
+        tree: &RTree<GeomWithData<(f64, f64), T>>,
+        p: PopulationPoint,
+        max_distance: f64,
+    ) -> impl Iterator<Item = &GeomWithData<(f64, f64), T>> {
+        self.locate_with_distance(tree, p, max_distance)
+            .map(|(station, _)| station)
+    }
+
+    /// Every station within max_distance ground meters of the population
+    /// point, with its ground distance
+    pub fn locate_with_distance<T>(
+        self,
+        tree: &RTree<GeomWithData<(f64, f64), T>>,
+        p: PopulationPoint,
+        max_distance: f64,
+    ) -> impl Iterator<Item = (&GeomWithData<(f64, f64), T>, f64)> {
+        let radius = self.search_radius(&p, max_distance);
+        tree.locate_within_distance(self.point_position(&p), radius * radius)
+            .map(move |station| (station, self.distance(&p, station.geom())))
+            .filter(move |(_, d)| *d <= max_distance)
+    }
+
+    /// The nearest station to the population point and its ground distance
+    pub fn nearest_neighbor<'a>(
+        self,
+        tree: &'a StationTree,
+        p: &PopulationPoint,
+    ) -> Option<(&'a StationPoint, f64)> {
+        let mut candidates =
+            tree.nearest_neighbor_iter_with_distance_2(&self.point_position(p));
+        if !self.on_sphere() {
+            return candidates.next().map(|(station, _)| {
+                (station, self.distance(p, station.geom()))
+            });
+        }
+
+        // the nearest station in the tree might not be the nearest on the
+        // ground, so keep looking until no station further away in the tree
+        // can be closer on the ground
+        let mut nearest: Option<(&StationPoint, f64)> = None;
+        for (station, d2) in candidates {
+            let tree_distance = d2.sqrt();
+            let lower_bound =
+                tree_distance / MARGIN * max_lat(p.lat, tree_distance).cos();
+            if matches!(nearest, Some((_, best)) if lower_bound > best) {
+                break;
+            }
+            let d = self.distance(p, station.geom());
+            if nearest.is_none_or(|(_, best)| d < best) {
+                nearest = Some((station, d));
+            }
+        }
+        nearest
+    }
+}
+
+/// The highest latitude (in radians) a point within distance of the given
+/// latitude can have, for the Mercator scale factor
+fn max_lat(lat: f64, distance: f64) -> f64 {
+    (lat.to_radians().abs() + distance / MERCATOR_RADIUS).min(FRAC_PI_2 - 1e-6)
+}
+
+/// Spherical Web Mercator, EPSG:3857, which is conformal so that the tree
+/// can search it with a scaled radius
+pub(crate) fn to_mercator(lon: f64, lat: f64) -> (f64, f64) {
+    let x = MERCATOR_RADIUS * lon.to_radians();
+    let y =
+        MERCATOR_RADIUS * (FRAC_PI_2 / 2.0 + lat.to_radians() / 2.0).tan().ln();
+    (x, y)
+}
+
+fn from_mercator((x, y): &(f64, f64)) -> Point {
+    let lon = (x / MERCATOR_RADIUS).to_degrees();
+    let lat =
+        (2.0 * (y / MERCATOR_RADIUS).exp().atan() - FRAC_PI_2).to_degrees();
+    Point::new(lon, lat)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn point(lon: f64, lat: f64) -> PopulationPoint {
+        let (x, y) = to_mercator(lon, lat);
+        PopulationPoint {
+            lon,
+            lat,
+            pop: 1.0,
+            x,
+            y,
+        }
+    }
+
+    fn station(lon: f64, lat: f64) -> Station {
+        let (x, y) = to_mercator(lon, lat);
+        Station {
+            name: String::new(),
+            lat,
+            lon,
+            x,
+            y,
+            weight: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_mercator_round_trip() {
+        let p = from_mercator(&to_mercator(139.76, 35.68));
+        assert!((p.x() - 139.76).abs() < 1e-9);
+        assert!((p.y() - 35.68).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_ground_meters() {
+        // about 1 km north of Bank station, London
+        let p = point(-0.0886, 51.5223);
+        let s = station(-0.0886, 51.5133);
+        for model in [DistanceModel::Geodesic, DistanceModel::Haversine] {
+            let d = model.distance(&p, &model.station_position(&s));
+            assert!((d - 1000.0).abs() < 5.0, "{:?}: {}", model, d);
+        }
+
+        // the planar distance in EPSG:3857 is inflated by a factor of
+        // about 1.6 at the latitude of London
+        let planar = DistanceModel::Planar.distance(&p, &(s.x, s.y));
+        assert!((planar - 1610.0).abs() < 10.0);
+        let mercator = DistanceModel::Mercator.distance(&p, &(s.x, s.y));
+        assert!((mercator - 1000.0).abs() < 5.0);
+    }
+
+    #[test]
+    fn test_within_and_nearest() {
+        let p = point(139.76, 35.68);
+        let stations = [
+            station(139.76, 35.689),
+            station(139.772, 35.68),
+            station(139.76, 35.70),
+        ];
+        for model in [
+            DistanceModel::Mercator,
+            DistanceModel::Haversine,
+            DistanceModel::Geodesic,
+        ] {
+            let tree = model.station_tree(&stations);
+            let n = model.locate_within_distance(&tree, p, 1100.0).count();
+            assert_eq!(n, 2, "{:?}", model);
+
+            let (nearest, d) = model.nearest_neighbor(&tree, &p).unwrap();
+            assert_eq!(nearest.data.id, 0);
+            assert!((d - 998.0).abs() < 5.0, "{:?}: {}", model, d);
+        }
+    }
+
+    #[test]
+    fn test_parse_model() {
+        assert_eq!("planar".parse(), Ok(DistanceModel::Planar));
+        assert!("euclid".parse::<DistanceModel>().is_err());
+    }
+}
@@ -0,0 +1,782 @@
+use crate::{column_index, parse_field, StationColumns};
+use csv::StringRecord;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs::File;
+use std::io::{Read, Seek, Write};
+use std::str::FromStr;
+use zip::ZipArchive;
+
+/// The columns that the GTFS stations have on top of the station columns
+pub const STOP_ID: &str = "stop_id";
+pub const MODES: &str = "modes";
+pub const LINES: &str = "lines";
+pub const DEPARTURES_PER_HOUR: &str = "departures_per_hour";
+
+/// The kind of transport of a route, from its GTFS route_type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Mode {
+    /// Metro, subway and underground
+    Tube,
+    /// Railways, including monorails and funiculars
+    Rail,
+    /// Trams, light rail and cable trams
+    Tram,
+    /// Buses, trolleybuses and coaches
+    Bus,
+    /// Ferries, aerial lifts and anything else
+    Other,
+}
+
+impl Mode {
+    /// Both the basic route types and the extended ones
+    pub fn from_route_type(route_type: u32) -> Self {
+        match route_type {
+            1 | 400..=499 => Self::Tube,
+            2 | 7 | 12 | 100..=199 | 1400..=1499 => Self::Rail,
+            0 | 5 | 900..=999 => Self::Tram,
+            3 | 11 | 200..=299 | 700..=899 => Self::Bus,
+            _ => Self::Other,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Tube => "tube",
+            Self::Rail => "rail",
+            Self::Tram => "tram",
+            Self::Bus => "bus",
+            Self::Other => "other",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tube" => Ok(Self::Tube),
+            "rail" => Ok(Self::Rail),
+            "tram" => Ok(Self::Tram),
+            "bus" => Ok(Self::Bus),
+            "other" => Ok(Self::Other),
+            _ => Err(format!(
+                "unknown mode `{}`, expected one of: \
+                 tube, rail, tram, bus, other",
+                s
+            )),
+        }
+    }
+}
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+/// The day whose services are counted: a weekday of the regular timetable,
+/// or a date, which also applies the start and end dates of the services
+/// and the exceptions in calendar_dates.txt
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceDay {
+    /// 0 is monday
+    Weekday(usize),
+    /// YYYYMMDD, as in the GTFS files
+    Date(u32),
+}
+
+impl ServiceDay {
+    fn weekday(self) -> usize {
+        match self {
+            Self::Weekday(day) => day,
+            Self::Date(date) => weekday_of(date),
+        }
+    }
+
+    /// The day before, whose trips past midnight run on this day
+    fn previous(self) -> Self {
+        match self {
+            Self::Weekday(day) => Self::Weekday((day + 6) % 7),
+            Self::Date(date) => Self::Date(day_before(date)),
+        }
+    }
+}
+
+impl FromStr for ServiceDay {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(day) = WEEKDAYS.iter().position(|d| *d == s) {
+            return Ok(Self::Weekday(day));
+        }
+        let date = s.parse().ok().filter(|_| s.len() == 8);
+        date.map(Self::Date).ok_or_else(|| {
+            format!("`{}` is neither a weekday nor a YYYYMMDD date", s)
+        })
+    }
+}
+
+/// The weekday of a YYYYMMDD date, 0 being monday
+fn weekday_of(date: u32) -> usize {
+    const OFFSETS: [u32; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let (mut y, m, d) = (date / 10000, date / 100 % 100, date % 100);
+    if m < 3 {
+        y -= 1;
+    }
+    // Sakamoto's method gives 0 for sunday
+    let sunday_first =
+        (y + y / 4 - y / 100 + y / 400 + OFFSETS[(m as usize + 11) % 12] + d)
+            % 7;
+    (sunday_first as usize + 6) % 7
+}
+
+/// The YYYYMMDD date before a date
+fn day_before(date: u32) -> u32 {
+    if date % 100 > 1 {
+        return date - 1;
+    }
+    let (y, m) = (date / 10000, date / 100 % 100);
+    let (y, m) = if m > 1 { (y, m - 1) } else { (y - 1, 12) };
+    let leap = y % 4 == 0 && (y % 100 != 0 || y % 400 == 0);
+    let days = match m {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    y * 10000 + m * 100 + days
+}
+
+/// The seconds in a day, which the times of the trips from the day before
+/// are past
+const DAY: u32 = 24 * 3600;
+
+/// A time of day in seconds. GTFS times can be past 24:00:00 for trips
+/// that started the day before
+fn parse_time(s: &str) -> Option<u32> {
+    let mut parts = s.trim().split(':').map(|p| p.parse::<u32>().ok());
+    let (h, m) = (parts.next()??, parts.next()??);
+    let s = parts.next().unwrap_or(Some(0))?;
+    Some(h * 3600 + m * 60 + s)
+}
+
+/// The hours whose departures are counted, as HH:MM-HH:MM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    /// In seconds, including the start and excluding the end
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Window {
+    fn hours(self) -> f64 {
+        (self.end - self.start) as f64 / 3600.0
+    }
+
+    fn contains(self, time: u32) -> bool {
+        (self.start..self.end).contains(&time)
+    }
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("`{}` is not a window like 07:00-10:00", s);
+        let (start, end) = s.split_once('-').ok_or_else(err)?;
+        let start = parse_time(start).ok_or_else(err)?;
+        let end = parse_time(end).ok_or_else(err)?;
+        if end <= start {
+            return Err(format!("the window `{}` ends before it starts", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Which services are read from the feed
+#[derive(Debug, Clone)]
+pub struct GtfsOptions {
+    pub day: ServiceDay,
+    pub window: Window,
+    /// Only the routes of these modes, and the stations they serve
+    pub modes: Vec<Mode>,
+}
+
+/// A station of the feed: a stop with location_type 1 and every stop whose
+/// parent_station it is, or a stop without a parent
+#[derive(Debug, Clone, PartialEq)]
+pub struct GtfsStation {
+    pub stop_id: String,
+    pub name: String,
+    pub lat: f64,
+    pub lon: f64,
+    /// The modes of the routes that stop at the station on the day
+    pub modes: Vec<Mode>,
+    /// The number of routes that stop at the station on the day
+    pub lines: usize,
+    /// The departures from the station within the window, per hour. The
+    /// trips from the day before count after midnight, and the last stop of
+    /// a trip isn't a departure
+    pub departures_per_hour: f64,
+}
+
+/// Read a GTFS feed in a zip, keeping the stations with a departure of the
+/// given modes on the day. The stations are in the order of stops.txt
+pub fn load_gtfs(
+    path: &str,
+    options: &GtfsOptions,
+) -> Result<Vec<GtfsStation>, Box<dyn std::error::Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let archive =
+        ZipArchive::new(file).map_err(|e| format!("{}: {}", path, e))?;
+    read_gtfs(archive, options).map_err(|e| format!("{}: {}", path, e).into())
+}
+
+/// The headers and rows of a file in the feed
+struct Table<'a> {
+    name: &'static str,
+    headers: StringRecord,
+    reader: csv::Reader<Box<dyn Read + 'a>>,
+}
+
+impl<'a> Table<'a> {
+    fn open<R: Read + Seek>(
+        archive: &'a mut ZipArchive<R>,
+        name: &'static str,
+    ) -> Result<Option<Self>, String> {
+        let file = match archive.by_name(name) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", name, e)),
+        };
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(Box::new(file) as Box<dyn Read>);
+        let headers = reader
+            .headers()
+            .map_err(|e| format!("{}: {}", name, e))?
+            .clone();
+        Ok(Some(Self {
+            name,
+            headers,
+            reader,
+        }))
+    }
+
+    fn required<R: Read + Seek>(
+        archive: &'a mut ZipArchive<R>,
+        name: &'static str,
+    ) -> Result<Self, String> {
+        Self::open(archive, name)?
+            .ok_or_else(|| format!("the feed has no {}", name))
+    }
+
+    fn column(&self, name: &str) -> Result<usize, String> {
+        column_index(&self.headers, name)
+            .map_err(|e| format!("{}: {}", self.name, e))
+    }
+
+    fn optional_column(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|h| h == name)
+    }
+
+    /// Call f on every row, adding the file name to its errors
+    fn for_each(
+        mut self,
+        mut f: impl FnMut(&StringRecord) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let mut record = StringRecord::new();
+        loop {
+            match self.reader.read_record(&mut record) {
+                Ok(true) => {
+                    f(&record).map_err(|e| format!("{}: {}", self.name, e))?
+                }
+                Ok(false) => return Ok(()),
+                Err(e) => return Err(format!("{}: {}", self.name, e)),
+            }
+        }
+    }
+}
+
+fn field(record: &StringRecord, idx: usize) -> &str {
+    record.get(idx).unwrap_or_default()
+}
+
+/// The services that run on the day
+fn active_services<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    day: ServiceDay,
+) -> Result<HashSet<String>, String> {
+    let mut services = HashSet::new();
+    match Table::open(archive, "calendar.txt")? {
+        None if matches!(day, ServiceDay::Weekday(_)) => {
+            return Err(
+                "the feed has no calendar.txt, so it needs a date".into()
+            )
+        }
+        None => {}
+        Some(calendar) => {
+            let id = calendar.column("service_id")?;
+            let runs = calendar.column(WEEKDAYS[day.weekday()])?;
+            let dates = match day {
+                ServiceDay::Date(date) => Some((
+                    calendar.column("start_date")?,
+                    calendar.column("end_date")?,
+                    date,
+                )),
+                ServiceDay::Weekday(_) => None,
+            };
+            calendar.for_each(|record| {
+                let within = dates.is_none_or(|(start, end, date)| {
+                    let start =
+                        field(record, start).parse().unwrap_or(u32::MAX);
+                    let end = field(record, end).parse().unwrap_or(0);
+                    (start..=end).contains(&date)
+                });
+                if within && field(record, runs) == "1" {
+                    services.insert(field(record, id).to_string());
+                }
+                Ok(())
+            })?;
+        }
+    }
+
+    let ServiceDay::Date(date) = day else {
+        return Ok(services);
+    };
+    let Some(exceptions) = Table::open(archive, "calendar_dates.txt")? else {
+        return Ok(services);
+    };
+    let id = exceptions.column("service_id")?;
+    let date_idx = exceptions.column("date")?;
+    let kind = exceptions.column("exception_type")?;
+    let date = date.to_string();
+    exceptions.for_each(|record| {
+        if field(record, date_idx) != date {
+            return Ok(());
+        }
+        let service = field(record, id).to_string();
+        match field(record, kind) {
+            "1" => services.insert(service),
+            "2" => services.remove(&service),
+            _ => false,
+        };
+        Ok(())
+    })?;
+    Ok(services)
+}
+
+/// What is known of the stations while the stop times are read
+#[derive(Default)]
+struct Served {
+    modes: BTreeSet<Mode>,
+    routes: HashSet<u32>,
+    departures: usize,
+}
+
+/// A trip of the selected modes that runs on the day, or past midnight of
+/// the day before
+struct Trip {
+    /// The position of the trip, for its last stop
+    index: usize,
+    route: u32,
+    mode: Mode,
+    on_day: bool,
+    from_day_before: bool,
+}
+
+/// The latest stop of a trip so far, which is only counted once a later
+/// stop shows that the trip doesn't end there
+#[derive(Clone, Copy)]
+struct LastStop {
+    sequence: u32,
+    station: usize,
+    /// Departs within the window
+    departs: bool,
+}
+
+pub fn read_gtfs<R: Read + Seek>(
+    mut archive: ZipArchive<R>,
+    options: &GtfsOptions,
+) -> Result<Vec<GtfsStation>, String> {
+    let services = active_services(&mut archive, options.day)?;
+    let services_before =
+        active_services(&mut archive, options.day.previous())?;
+
+    // the index and mode of every route of the selected modes
+    let mut routes = HashMap::new();
+    let table = Table::required(&mut archive, "routes.txt")?;
+    let (id, kind) = (table.column("route_id")?, table.column("route_type")?);
+    table.for_each(|record| {
+        let route_type = parse_field(record, kind, "route_type")?;
+        let mode = Mode::from_route_type(route_type as u32);
+        if options.modes.contains(&mode) {
+            let i = routes.len() as u32;
+            routes.insert(field(record, id).to_string(), (i, mode));
+        }
+        Ok(())
+    })?;
+
+    // the route of every trip that runs on the day or the day before
+    let mut trips = HashMap::new();
+    let table = Table::required(&mut archive, "trips.txt")?;
+    let trip = table.column("trip_id")?;
+    let route = table.column("route_id")?;
+    let service = table.column("service_id")?;
+    table.for_each(|record| {
+        let on_day = services.contains(field(record, service));
+        let from_day_before = services_before.contains(field(record, service));
+        if !on_day && !from_day_before {
+            return Ok(());
+        }
+        if let Some((route, mode)) = routes.get(field(record, route)) {
+            trips.insert(
+                field(record, trip).to_string(),
+                Trip {
+                    index: trips.len(),
+                    route: *route,
+                    mode: *mode,
+                    on_day,
+                    from_day_before,
+                },
+            );
+        }
+        Ok(())
+    })?;
+
+    // every stop belongs to its parent station, or is a station itself
+    let mut stations: Vec<GtfsStation> = vec![];
+    let mut station_of = HashMap::new();
+    let mut parents = vec![];
+    let table = Table::required(&mut archive, "stops.txt")?;
+    let stop = table.column("stop_id")?;
+    let name = table.column("stop_name")?;
+    let (lat, lon) = (table.column("stop_lat")?, table.column("stop_lon")?);
+    let location_type = table.optional_column("location_type");
+    let parent = table.optional_column("parent_station");
+    table.for_each(|record| {
+        let stop_id = field(record, stop).to_string();
+        let parent = parent.map_or("", |p| field(record, p));
+        if !parent.is_empty() {
+            parents.push((stop_id, parent.to_string()));
+            return Ok(());
+        }
+        // entrances, generic nodes and boarding areas always have parents
+        let kind = location_type.map_or("", |t| field(record, t));
+        if !matches!(kind, "" | "0" | "1") {
+            return Ok(());
+        }
+        station_of.insert(stop_id.clone(), stations.len());
+        stations.push(GtfsStation {
+            stop_id,
+            name: field(record, name).to_string(),
+            lat: parse_field(record, lat, "stop_lat")?,
+            lon: parse_field(record, lon, "stop_lon")?,
+            modes: vec![],
+            lines: 0,
+            departures_per_hour: 0.0,
+        });
+        Ok(())
+    })?;
+    for (stop, parent) in parents {
+        // platforms of a parent that is itself a child, or missing, are
+        // left out
+        if let Some(i) = station_of.get(&parent) {
+            station_of.insert(stop, *i);
+        }
+    }
+
+    let mut served: Vec<Served> =
+        stations.iter().map(|_| Served::default()).collect();
+    let table = Table::required(&mut archive, "stop_times.txt")?;
+    let trip = table.column("trip_id")?;
+    let stop = table.column("stop_id")?;
+    let sequence = table.column("stop_sequence")?;
+    let departure = table.column("departure_time")?;
+    let arrival = table.optional_column("arrival_time");
+    let window = options.window;
+    let mut last_stops: Vec<Option<LastStop>> = vec![None; trips.len()];
+    table.for_each(|record| {
+        let Some(trip) = trips.get(field(record, trip)) else {
+            return Ok(());
+        };
+        let Some(station) = station_of.get(field(record, stop)) else {
+            return Ok(());
+        };
+        // untimed stops between timepoints aren't counted
+        let time = parse_time(field(record, departure))
+            .or_else(|| parse_time(field(record, arrival?)));
+        let after_midnight = time.filter(|t| *t >= DAY).map(|t| t - DAY);
+        let departs = (trip.on_day && time.is_some_and(|t| window.contains(t)))
+            || (trip.from_day_before
+                && after_midnight.is_some_and(|t| window.contains(t)));
+        // the trips from the day before only serve the day after midnight
+        if trip.on_day || after_midnight.is_some() {
+            served[*station].modes.insert(trip.mode);
+            served[*station].routes.insert(trip.route);
+        }
+
+        // the stop times aren't always in order, so a stop is counted
+        // when a later stop of its trip is read, and the last is left out
+        let this = LastStop {
+            sequence: parse_field(record, sequence, "stop_sequence")? as u32,
+            station: *station,
+            departs,
+        };
+        let last = &mut last_stops[trip.index];
+        let earlier = match last {
+            Some(l) if l.sequence > this.sequence => Some(this),
+            _ => last.replace(this),
+        };
+        if let Some(earlier) = earlier.filter(|s| s.departs) {
+            served[earlier.station].departures += 1;
+        }
+        Ok(())
+    })?;
+
+    let hours = options.window.hours();
+    Ok(stations
+        .into_iter()
+        .zip(served)
+        .filter(|(_, served)| !served.modes.is_empty())
+        .map(|(station, served)| GtfsStation {
+            modes: served.modes.into_iter().collect(),
+            lines: served.routes.len(),
+            departures_per_hour: served.departures as f64 / hours,
+            ..station
+        })
+        .collect())
+}
+
+/// Write the stations in the layout of the station files, with the x and y
+/// of every station and the GTFS columns after them
+pub fn write_gtfs_stations<W: Write>(
+    writer: W,
+    stations: &[GtfsStation],
+    xys: &[(f64, f64)],
+    columns: &StationColumns,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        columns.name.as_str(),
+        &columns.lat,
+        &columns.lon,
+        "x",
+        "y",
+        STOP_ID,
+        MODES,
+        LINES,
+        DEPARTURES_PER_HOUR,
+    ])?;
+    for (s, (x, y)) in stations.iter().zip(xys) {
+        let modes: Vec<_> = s.modes.iter().map(|m| m.name()).collect();
+        writer.write_record([
+            s.name.clone(),
+            s.lat.to_string(),
+            s.lon.to_string(),
+            x.to_string(),
+            y.to_string(),
+            s.stop_id.clone(),
+            modes.join(";"),
+            s.lines.to_string(),
+            s.departures_per_hour.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn save_gtfs_stations(
+    path: &str,
+    stations: &[GtfsStation],
+    xys: &[(f64, f64)],
+    columns: &StationColumns,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("cannot write {}: {}", path, e))?;
+    write_gtfs_stations(file, stations, xys, columns)
+        .map_err(|e| format!("{}: {}", path, e).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::read_stations;
+    use std::io::Cursor;
+    use zip::write::SimpleFileOptions;
+
+    fn feed(files: &[(&str, &str)]) -> ZipArchive<Cursor<Vec<u8>>> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in files {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        ZipArchive::new(zip.finish().unwrap()).unwrap()
+    }
+
+    const STOPS: &str = "\
+stop_id,stop_name,stop_lat,stop_lon,location_type,parent_station
+BNK,Bank,51.513,-0.089,1,
+BNK1,Bank Platform 1,51.5131,-0.0891,0,BNK
+BNK2,Bank Platform 2,51.5129,-0.0889,0,BNK
+BNKE,Bank Entrance,51.5135,-0.0889,2,BNK
+LST,Liverpool Street,51.518,-0.081,,
+MRG,Moorgate,51.5186,-0.0886,,
+STP,Bus stop,51.52,-0.08,0,
+";
+
+    const ROUTES: &str = "\
+route_id,route_type
+central,1
+dlr,0
+elizabeth,100
+bus,3
+";
+
+    const TRIPS: &str = "\
+route_id,service_id,trip_id
+central,weekday,c1
+central,weekday,c2
+central,weekend,c3
+dlr,weekday,d1
+elizabeth,weekday,e1
+bus,weekday,b1
+";
+
+    const STOP_TIMES: &str = "\
+trip_id,arrival_time,departure_time,stop_id,stop_sequence
+c1,07:15:00,07:16:00,LST,2
+c1,07:10:00,07:11:00,BNK1,1
+c1,07:20:00,,MRG,3
+c2,09:59:00,,BNK2,1
+c2,,,LST,2
+c2,10:05:00,,MRG,3
+c3,08:00:00,08:00:00,BNK1,1
+c3,08:05:00,,MRG,2
+d1,10:30:00,10:30:00,BNK2,1
+d1,10:35:00,,MRG,2
+e1,31:30:00,31:30:00,LST,1
+e1,31:40:00,,MRG,2
+b1,08:00:00,08:00:00,STP,1
+b1,08:10:00,,MRG,2
+";
+
+    const CALENDAR: &str = "\
+service_id,monday,tuesday,wednesday,thursday,friday,saturday,sunday,start_date,end_date
+weekday,1,1,1,1,1,0,0,20240101,20241231
+weekend,0,0,0,0,0,1,1,20240101,20241231
+";
+
+    fn options(day: &str) -> GtfsOptions {
+        GtfsOptions {
+            day: day.parse().unwrap(),
+            window: "07:00-10:00".parse().unwrap(),
+            modes: vec![Mode::Tube, Mode::Rail, Mode::Tram],
+        }
+    }
+
+    #[test]
+    fn test_read_gtfs() {
+        let files = [
+            ("stops.txt", STOPS),
+            ("routes.txt", ROUTES),
+            ("trips.txt", TRIPS),
+            ("stop_times.txt", STOP_TIMES),
+            ("calendar.txt", CALENDAR),
+        ];
+        let stations = read_gtfs(feed(&files), &options("monday")).unwrap();
+        assert_eq!(stations.len(), 3);
+
+        let bank = &stations[0];
+        assert_eq!((bank.name.as_str(), bank.lat), ("Bank", 51.513));
+        assert_eq!(bank.modes, [Mode::Tube, Mode::Tram]);
+        assert_eq!(bank.lines, 2);
+        // c1 and c2 in the window, d1 after it
+        assert_eq!(bank.departures_per_hour, 2.0 / 3.0);
+
+        let lst = &stations[1];
+        assert_eq!(lst.modes, [Mode::Tube, Mode::Rail]);
+        // c2 has no time at liverpool street, and e1 is past midnight
+        assert_eq!(lst.departures_per_hour, 1.0 / 3.0);
+
+        // every trip ends at moorgate
+        let mrg = &stations[2];
+        assert_eq!(mrg.modes, [Mode::Tube, Mode::Rail, Mode::Tram]);
+        assert_eq!(mrg.departures_per_hour, 0.0);
+
+        let weekend = read_gtfs(feed(&files), &options("sunday")).unwrap();
+        assert_eq!(weekend.len(), 2);
+        assert_eq!(weekend[0].departures_per_hour, 1.0 / 3.0);
+
+        // e1 of monday leaves liverpool street at 07:30 on tuesday
+        let tuesday = read_gtfs(feed(&files), &options("tuesday")).unwrap();
+        assert_eq!(tuesday[1].departures_per_hour, 2.0 / 3.0);
+    }
+
+    #[test]
+    fn test_calendar_dates() {
+        let dates = "service_id,date,exception_type\n\
+                     weekday,20240101,2\n\
+                     weekend,20240101,1\n";
+        let files = [
+            ("stops.txt", STOPS),
+            ("routes.txt", ROUTES),
+            ("trips.txt", TRIPS),
+            ("stop_times.txt", STOP_TIMES),
+            ("calendar.txt", CALENDAR),
+            ("calendar_dates.txt", dates),
+        ];
+        // a bank holiday monday runs the weekend timetable
+        let stations = read_gtfs(feed(&files), &options("20240101")).unwrap();
+        let ids: Vec<_> = stations.iter().map(|s| s.stop_id.as_str()).collect();
+        assert_eq!(ids, ["BNK", "MRG"]);
+
+        // after the end of the timetable
+        let stations = read_gtfs(feed(&files), &options("20250106")).unwrap();
+        assert!(stations.is_empty());
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(weekday_of(20240101), 0);
+        assert_eq!(weekday_of(20241020), 6);
+        assert_eq!(weekday_of(20000229), 1);
+        assert_eq!(day_before(20240301), 20240229);
+        assert_eq!(day_before(20250101), 20241231);
+        assert_eq!(day_before(20241016), 20241015);
+        assert_eq!(ServiceDay::Weekday(0).previous(), ServiceDay::Weekday(6));
+        assert_eq!(parse_time(" 7:05:30"), Some(7 * 3600 + 330));
+        assert_eq!(parse_time("25:00:00"), Some(90000));
+        assert!("10:00-07:00".parse::<Window>().is_err());
+        assert!("friday".parse::<ServiceDay>().is_ok());
+        assert!("2024".parse::<ServiceDay>().is_err());
+    }
+
+    #[test]
+    fn test_write_gtfs_stations() {
+        let station = GtfsStation {
+            stop_id: "BNK".into(),
+            name: "Bank".into(),
+            lat: 51.513,
+            lon: -0.089,
+            modes: vec![Mode::Tube, Mode::Tram],
+            lines: 2,
+            departures_per_hour: 1.5,
+        };
+        let mut csv = vec![];
+        let columns = StationColumns::default();
+        write_gtfs_stations(&mut csv, &[station], &[(1.0, 2.0)], &columns)
+            .unwrap();
+        let text = String::from_utf8(csv.clone()).unwrap();
+        assert!(text.ends_with("Bank,51.513,-0.089,1,2,BNK,tube;tram,2,1.5\n"));
+        let stations = read_stations(&csv[..], &columns).unwrap();
+        assert_eq!((stations[0].x, stations[0].y), (1.0, 2.0));
+    }
+}
@@ -1,7 +1,43 @@
+pub mod accessibility;
+pub mod autocorrelation;
+pub mod boundaries;
+pub mod cache;
+pub mod catchment;
+pub mod config;
+pub mod count;
 pub mod data_wrangling;
-pub mod traits;
+pub mod decay;
+pub mod distance;
+pub mod gtfs;
+pub mod nearest;
+pub mod network;
+pub mod osm;
+pub mod osm_stations;
 pub mod plotting;
+pub mod point_layer;
+pub mod quadrant;
+pub mod reproject;
+pub mod stream;
+pub mod traits;
 
+pub use accessibility::*;
+pub use autocorrelation::*;
+pub use boundaries::*;
+pub use cache::*;
+pub use catchment::*;
+pub use config::*;
+pub use count::*;
 pub use data_wrangling::*;
-pub use traits::*;
+pub use decay::*;
+pub use distance::*;
+pub use gtfs::*;
+pub use nearest::*;
+pub use network::*;
+pub use osm::*;
+pub use osm_stations::*;
 pub use plotting::*;
+pub use point_layer::*;
+pub use quadrant::*;
+pub use reproject::*;
+pub use stream::*;
+pub use traits::*;
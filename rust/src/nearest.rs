@@ -0,0 +1,188 @@
+use crate::{
+    Decay, DistanceModel, PopulationPoint, StationRecord, StationTree,
+};
+use rayon::prelude::*;
+
+/// The ground distance from every population point to its nearest station,
+/// in the same order as the points. Infinite if there are no stations
+pub fn nearest_distances(
+    tree: &StationTree,
+    points: &[PopulationPoint],
+    model: DistanceModel,
+) -> Vec<f64> {
+    points
+        .par_iter()
+        .map(|p| {
+            model
+                .nearest_neighbor(tree, p)
+                .map_or(f64::INFINITY, |(_, d)| d)
+        })
+        .collect()
+}
+
+/// The nearest station to every population point and its ground distance,
+/// in the same order as the points. None if there are no stations
+pub fn nearest_stations<'a>(
+    tree: &'a StationTree,
+    points: &[PopulationPoint],
+    model: DistanceModel,
+) -> Vec<Option<(&'a StationRecord, f64)>> {
+    points
+        .par_iter()
+        .map(|p| {
+            let (station, d) = model.nearest_neighbor(tree, p)?;
+            Some((&station.data, d))
+        })
+        .collect()
+}
+
+/// The population sorted by the distance to the nearest station, so that
+/// the population within any distance is a binary search away
+pub struct CumulativePop {
+    distances: Vec<f64>,
+    /// The (weighted) population of every point up to and including this
+    /// index
+    cum_pop: Vec<f64>,
+    total: f64,
+}
+
+impl CumulativePop {
+    /// nearest are the distances from nearest_distances
+    pub fn new(points: &[PopulationPoint], nearest: &[f64]) -> Self {
+        Self::build(points, nearest, |_| 1.0)
+    }
+
+    /// Like new, but the population of every point is weighted by the decay
+    /// of the distance to its nearest station, so that the proportions are
+    /// the decay-weighted coverage within every distance. Points without a
+    /// station weigh 0, and the total is still the unweighted population
+    pub fn with_decay(
+        points: &[PopulationPoint],
+        nearest: &[f64],
+        decay: Decay,
+    ) -> Self {
+        Self::build(points, nearest, |d| {
+            if d.is_finite() {
+                decay.weight(d)
+            } else {
+                0.0
+            }
+        })
+    }
+
+    fn build(
+        points: &[PopulationPoint],
+        nearest: &[f64],
+        weight: impl Fn(f64) -> f64,
+    ) -> Self {
+        let mut pairs: Vec<_> = nearest
+            .iter()
+            .copied()
+            .zip(points.iter().map(|p| p.pop))
+            .collect();
+        pairs.par_sort_unstable_by(|a, b| a.0.total_cmp(&b.0));
+
+        let distances = pairs.iter().map(|(d, _)| *d).collect();
+        let cum_pop: Vec<_> = pairs
+            .iter()
+            .scan(0.0, |sum, (d, pop)| {
+                *sum += pop * weight(*d);
+                Some(*sum)
+            })
+            .collect();
+        let total = pairs.iter().map(|(_, pop)| pop).sum();
+
+        Self {
+            distances,
+            cum_pop,
+            total,
+        }
+    }
+
+    /// The total population of the points
+    pub fn total(&self) -> f64 {
+        self.total
+    }
+
+    /// The population within max_distance of a station
+    pub fn pop_within(&self, max_distance: f64) -> f64 {
+        match self.distances.partition_point(|d| *d <= max_distance) {
+            0 => 0.0,
+            n => self.cum_pop[n - 1],
+        }
+    }
+
+    /// The proportion of the population within max_distance of a station
+    pub fn prop_within(&self, max_distance: f64) -> f64 {
+        self.pop_within(max_distance) / self.total
+    }
+
+    /// The (weighted) population within any distance of a station as a
+    /// proportion of the total. With a decay this is the coverage index,
+    /// the population-weighted mean decay weight of the points
+    pub fn coverage(&self) -> f64 {
+        self.cum_pop.last().copied().unwrap_or(0.0) / self.total
+    }
+
+    /// The exact empirical cumulative distribution: every distinct distance
+    /// and the population within it
+    pub fn ecdf(&self) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.distances
+            .iter()
+            .zip(&self.cum_pop)
+            .enumerate()
+            .filter(|(i, (d, _))| {
+                d.is_finite() && self.distances.get(i + 1) != Some(d)
+            })
+            .map(|(_, (d, pop))| (*d, *pop))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn points(pops: &[f64]) -> Vec<PopulationPoint> {
+        pops.iter()
+            .map(|&pop| PopulationPoint {
+                lon: 0.0,
+                lat: 0.0,
+                pop,
+                x: 0.0,
+                y: 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pop_within() {
+        let points = points(&[1.0, 2.0, 3.0, 4.0]);
+        let nearest = [300.0, 100.0, f64::INFINITY, 100.0];
+        let c = CumulativePop::new(&points, &nearest);
+        assert_eq!(c.total(), 10.0);
+        assert_eq!(c.pop_within(50.0), 0.0);
+        assert_eq!(c.pop_within(100.0), 6.0);
+        assert_eq!(c.pop_within(299.0), 6.0);
+        assert_eq!(c.prop_within(300.0), 0.7);
+    }
+
+    #[test]
+    fn test_ecdf() {
+        let points = points(&[1.0, 2.0, 3.0, 4.0]);
+        let nearest = [300.0, 100.0, f64::INFINITY, 100.0];
+        let c = CumulativePop::new(&points, &nearest);
+        assert_eq!(c.ecdf().collect::<Vec<_>>(), [(100.0, 6.0), (300.0, 7.0)]);
+    }
+
+    #[test]
+    fn test_with_decay() {
+        let points = points(&[1.0, 2.0, 3.0, 4.0]);
+        let nearest = [300.0, 100.0, f64::INFINITY, 100.0];
+        let decay = Decay::Linear { max: 400.0 };
+        let c = CumulativePop::with_decay(&points, &nearest, decay);
+        assert_eq!(c.total(), 10.0);
+        assert_eq!(c.pop_within(100.0), 4.5);
+        assert_eq!(c.pop_within(1000.0), 4.75);
+        assert_eq!(c.coverage(), 0.475);
+    }
+}
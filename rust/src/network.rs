@@ -0,0 +1,708 @@
+use crate::count::sum_nested;
+use crate::distance::to_mercator;
+use crate::osm::{read_osm, tag, OsmElement, Tags};
+use crate::{
+    count_within_thresholds, nearest_distances, nearest_stations,
+    stations_within, sum_weights_within_thresholds, DistanceModel,
+    PopulationPoint, Station, StationRecord, StationTree,
+};
+use geo::{Distance, Haversine, Point};
+use rayon::prelude::*;
+use rstar::primitives::GeomWithData;
+use rstar::RTree;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::ops::AddAssign;
+
+/// The highway values that can be walked along, unless tagged otherwise.
+/// Motorways, and roads under construction or only proposed, are left out
+const WALKABLE: [&str; 26] = [
+    "footway",
+    "path",
+    "pedestrian",
+    "steps",
+    "corridor",
+    "crossing",
+    "platform",
+    "living_street",
+    "residential",
+    "service",
+    "unclassified",
+    "road",
+    "track",
+    "cycleway",
+    "bridleway",
+    "tertiary",
+    "tertiary_link",
+    "secondary",
+    "secondary_link",
+    "primary",
+    "primary_link",
+    "trunk",
+    "trunk_link",
+    "busway",
+    "elevator",
+    "escalator",
+];
+
+/// Whether a way is part of the pedestrian network
+pub fn is_walkable(tags: &Tags) -> bool {
+    let Some(highway) = tag(tags, "highway") else {
+        return false;
+    };
+    match tag(tags, "foot") {
+        Some("no") => return false,
+        Some("yes" | "designated" | "permissive") => return true,
+        _ => {}
+    }
+    if matches!(tag(tags, "access"), Some("no" | "private")) {
+        return false;
+    }
+    WALKABLE.contains(&highway)
+}
+
+/// A graph node in Web Mercator and its index
+type NodePosition = GeomWithData<[f64; 2], u32>;
+
+/// The pedestrian network of an OSM extract, as an undirected graph whose
+/// edges are the segments of the walkable ways, weighted by their length in
+/// meters. Only the largest connected component is kept, so that points
+/// aren't snapped onto an isolated footpath that no station can be reached
+/// from
+pub struct WalkGraph {
+    /// The lon and lat of every node
+    coords: Vec<(f64, f64)>,
+    /// The edges of node i are edges[offsets[i]..offsets[i + 1]]
+    offsets: Vec<usize>,
+    /// The other node and the length of every edge
+    edges: Vec<(u32, f64)>,
+    tree: RTree<NodePosition>,
+}
+
+/// Where a population point or a station joins the network: the nearest
+/// node and the straight-line distance to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Snapped {
+    pub node: u32,
+    pub offset: f64,
+}
+
+fn find(parents: &mut [u32], mut i: u32) -> u32 {
+    while parents[i as usize] != i {
+        let parent = parents[parents[i as usize] as usize];
+        parents[i as usize] = parent;
+        i = parent;
+    }
+    i
+}
+
+impl WalkGraph {
+    /// Build the graph from the walkable ways of a local .osm.pbf or .osm
+    /// extract
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut coords = HashMap::new();
+        let mut ways = vec![];
+        read_osm(path, |element| match element {
+            OsmElement::Node(n) => {
+                coords.insert(n.id, (n.lon, n.lat));
+            }
+            OsmElement::Way(w) if is_walkable(&w.tags) => ways.push(w.refs),
+            OsmElement::Way(_) => {}
+        })?;
+
+        let graph = Self::new(&coords, &ways);
+        if graph.coords.is_empty() {
+            return Err(format!("{} has no walkable ways", path).into());
+        }
+        Ok(graph)
+    }
+
+    /// Build the graph from the node coordinates and the node IDs along
+    /// every way. Nodes that are missing, such as the ones cut off at the
+    /// edge of an extract, break the way
+    pub fn new(coords: &HashMap<i64, (f64, f64)>, ways: &[Vec<i64>]) -> Self {
+        let mut index = HashMap::new();
+        let mut nodes = vec![];
+        let mut segments = vec![];
+        let mut node_of = |id: i64| -> Option<u32> {
+            let xy = coords.get(&id)?;
+            Some(*index.entry(id).or_insert_with(|| {
+                nodes.push(*xy);
+                nodes.len() as u32 - 1
+            }))
+        };
+        for refs in ways {
+            for pair in refs.windows(2) {
+                if let (Some(a), Some(b)) = (node_of(pair[0]), node_of(pair[1]))
+                {
+                    if a != b {
+                        segments.push((a, b));
+                    }
+                }
+            }
+        }
+
+        // keep the largest connected component
+        let mut parents: Vec<u32> = (0..nodes.len() as u32).collect();
+        for (a, b) in &segments {
+            let (a, b) = (find(&mut parents, *a), find(&mut parents, *b));
+            parents[a as usize] = b;
+        }
+        let mut sizes = vec![0; nodes.len()];
+        for i in 0..nodes.len() as u32 {
+            sizes[find(&mut parents, i) as usize] += 1;
+        }
+        let largest = (0..nodes.len()).max_by_key(|i| sizes[*i]);
+        let mut kept = vec![u32::MAX; nodes.len()];
+        let mut graph_coords = vec![];
+        for (i, xy) in nodes.iter().enumerate() {
+            if Some(find(&mut parents, i as u32) as usize) == largest {
+                kept[i] = graph_coords.len() as u32;
+                graph_coords.push(*xy);
+            }
+        }
+
+        let mut adjacent = vec![vec![]; graph_coords.len()];
+        for (a, b) in segments {
+            let (a, b) = (kept[a as usize], kept[b as usize]);
+            if a == u32::MAX {
+                continue;
+            }
+            let (pa, pb) = (graph_coords[a as usize], graph_coords[b as usize]);
+            let length = Haversine::distance(Point::from(pa), Point::from(pb));
+            adjacent[a as usize].push((b, length));
+            adjacent[b as usize].push((a, length));
+        }
+        let mut offsets = vec![0];
+        let mut edges = vec![];
+        for node_edges in adjacent {
+            edges.extend(node_edges);
+            offsets.push(edges.len());
+        }
+
+        let positions = graph_coords
+            .iter()
+            .enumerate()
+            .map(|(i, (lon, lat))| {
+                let (x, y) = to_mercator(*lon, *lat);
+                NodePosition::new([x, y], i as u32)
+            })
+            .collect();
+
+        Self {
+            coords: graph_coords,
+            offsets,
+            edges,
+            tree: RTree::bulk_load(positions),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.coords.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    fn edges(&self, node: u32) -> &[(u32, f64)] {
+        let node = node as usize;
+        &self.edges[self.offsets[node]..self.offsets[node + 1]]
+    }
+
+    /// The nearest node to a position. Mercator is conformal, so the
+    /// nearest node in it is the nearest on the ground at city scale
+    pub fn snap(&self, lon: f64, lat: f64) -> Option<Snapped> {
+        let (x, y) = to_mercator(lon, lat);
+        let nearest = self.tree.nearest_neighbor(&[x, y])?;
+        let node = self.coords[nearest.data as usize];
+        Some(Snapped {
+            node: nearest.data,
+            offset: Haversine::distance(
+                Point::new(lon, lat),
+                Point::from(node),
+            ),
+        })
+    }
+
+    /// The walking distance from the nearest source to every node, and the
+    /// index of that source, where every source starts at its offset. Nodes
+    /// further than max_distance are left out
+    fn dijkstra(
+        &self,
+        sources: impl IntoIterator<Item = Snapped>,
+        max_distance: f64,
+    ) -> HashMap<u32, (f64, usize)> {
+        let mut dist = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        for (i, s) in sources.into_iter().enumerate() {
+            if s.offset <= max_distance {
+                heap.push(State(s.offset, s.node, i));
+            }
+        }
+        while let Some(State(d, node, source)) = heap.pop() {
+            if dist.contains_key(&node) {
+                continue;
+            }
+            dist.insert(node, (d, source));
+            for (next, length) in self.edges(node) {
+                let next_d = d + length;
+                if next_d <= max_distance && !dist.contains_key(next) {
+                    heap.push(State(next_d, *next, source));
+                }
+            }
+        }
+        dist
+    }
+}
+
+/// A node, its distance and the source it was reached from in the Dijkstra
+/// queue, ordered so that the max-heap pops the nearest first
+#[derive(PartialEq)]
+struct State(f64, u32, usize);
+
+impl Eq for State {}
+
+impl Ord for State {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .0
+            .total_cmp(&self.0)
+            .then(self.1.cmp(&other.1))
+            .then(self.2.cmp(&other.2))
+    }
+}
+
+impl PartialOrd for State {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The walking network with the stations snapped onto it. A walking
+/// distance is the straight line from the point to its node, the shortest
+/// path along the network, then the straight line from the station's node
+/// to the station
+pub struct Network {
+    graph: WalkGraph,
+    stations: Vec<SnappedStation>,
+}
+
+/// Where a station joins the network
+struct SnappedStation {
+    snapped: Snapped,
+    /// The row of the station, as in StationRecord
+    id: usize,
+    weight: f64,
+}
+
+impl Network {
+    pub fn new(graph: WalkGraph, stations: &[Station]) -> Self {
+        let stations = stations
+            .iter()
+            .enumerate()
+            .filter_map(|(id, s)| {
+                Some(SnappedStation {
+                    snapped: graph.snap(s.lon, s.lat)?,
+                    id,
+                    weight: s.weight,
+                })
+            })
+            .collect();
+        Self { graph, stations }
+    }
+
+    fn snap_points(&self, points: &[PopulationPoint]) -> Vec<Option<Snapped>> {
+        points
+            .par_iter()
+            .map(|p| self.graph.snap(p.lon, p.lat))
+            .collect()
+    }
+
+    /// The walking distance from every point to its nearest station, found
+    /// with one Dijkstra search from all stations at once. Infinite if the
+    /// network has no stations
+    pub fn nearest_distances(&self, points: &[PopulationPoint]) -> Vec<f64> {
+        self.nearest_stations(points)
+            .into_iter()
+            .map(|nearest| nearest.map_or(f64::INFINITY, |(_, d)| d))
+            .collect()
+    }
+
+    /// The ID of the nearest station to every point and its walking
+    /// distance, with the same search as nearest_distances. None if the
+    /// network has no stations
+    pub fn nearest_stations(
+        &self,
+        points: &[PopulationPoint],
+    ) -> Vec<Option<(usize, f64)>> {
+        let sources = self.stations.iter().map(|s| s.snapped);
+        let dist = self.graph.dijkstra(sources, f64::INFINITY);
+        self.snap_points(points)
+            .into_iter()
+            .map(|snapped| {
+                let s = snapped?;
+                let (d, source) = dist.get(&s.node)?;
+                Some((self.stations[*source].id, s.offset + d))
+            })
+            .collect()
+    }
+
+    /// The number of stations within every walking threshold of every
+    /// point, indexed like count_within_thresholds
+    pub fn count_within_thresholds(
+        &self,
+        points: &[PopulationPoint],
+        thresholds: &[f64],
+    ) -> Vec<Vec<u32>> {
+        self.sum_within(points, thresholds, |_| 1)
+    }
+
+    /// The sum of the weights of the stations within every walking
+    /// threshold of every point, indexed like count_within_thresholds
+    pub fn sum_weights_within_thresholds(
+        &self,
+        points: &[PopulationPoint],
+        thresholds: &[f64],
+    ) -> Vec<Vec<f64>> {
+        self.sum_within(points, thresholds, |weight| weight)
+    }
+
+    /// The IDs of the stations within max_distance of every point along
+    /// the network, with their walking distances
+    pub fn stations_within(
+        &self,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<Vec<(usize, f64)>> {
+        let snapped = self.snap_points(points);
+        let at_node = self.reach(&snapped, max_distance);
+        snapped
+            .par_iter()
+            .map(|snapped| {
+                let Some(s) = snapped else {
+                    return vec![];
+                };
+                at_node[s.node as usize]
+                    .iter()
+                    .map(|(d, i)| (self.stations[*i].id, d + s.offset))
+                    .filter(|(_, d)| *d <= max_distance)
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn sum_within<V>(
+        &self,
+        points: &[PopulationPoint],
+        thresholds: &[f64],
+        value: impl Fn(f64) -> V + Sync,
+    ) -> Vec<Vec<V>>
+    where
+        V: Copy + Default + AddAssign + Send + Sync,
+    {
+        let max_distance = thresholds.iter().copied().fold(0.0, f64::max);
+        let snapped = self.snap_points(points);
+        let at_node = self.reach(&snapped, max_distance);
+
+        let (at_node, value) = (&at_node, &value);
+        sum_nested(&snapped, thresholds, |snapped, _| {
+            (*snapped).into_iter().flat_map(move |s| {
+                at_node[s.node as usize].iter().map(move |(d, i)| {
+                    (d + s.offset, value(self.stations[*i].weight))
+                })
+            })
+        })
+    }
+
+    /// The distance to every station within max_distance of every node
+    /// that a point is snapped to, with the index of the station in
+    /// self.stations. Every station is searched from once, and only the
+    /// distances to the nodes that points are snapped to are kept
+    fn reach(
+        &self,
+        snapped: &[Option<Snapped>],
+        max_distance: f64,
+    ) -> Vec<Vec<(f64, usize)>> {
+        let mut is_target = vec![false; self.graph.len()];
+        for s in snapped.iter().flatten() {
+            is_target[s.node as usize] = true;
+        }
+
+        let reached: Vec<Vec<(u32, f64, usize)>> = self
+            .stations
+            .par_iter()
+            .enumerate()
+            .map(|(i, station)| {
+                self.graph
+                    .dijkstra([station.snapped], max_distance)
+                    .into_iter()
+                    .filter(|(node, _)| is_target[*node as usize])
+                    .map(|(node, (d, _))| (node, d, i))
+                    .collect()
+            })
+            .collect();
+        let mut at_node = vec![vec![]; self.graph.len()];
+        for (node, d, i) in reached.into_iter().flatten() {
+            at_node[node as usize].push((d, i));
+        }
+        at_node
+    }
+}
+
+/// How the distances from the population points to the stations are
+/// measured: in a straight line with a distance model, or along the walking
+/// network
+#[derive(Clone, Copy)]
+pub enum Metric<'a> {
+    Straight(DistanceModel),
+    Walking(&'a Network),
+}
+
+impl<'a> Metric<'a> {
+    /// Walking if there is a network, otherwise the distance model
+    pub fn new(model: DistanceModel, network: Option<&'a Network>) -> Self {
+        match network {
+            Some(network) => Self::Walking(network),
+            None => Self::Straight(model),
+        }
+    }
+
+    /// The distance from every point to its nearest station
+    pub fn nearest_distances(
+        self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+    ) -> Vec<f64> {
+        match self {
+            Self::Straight(model) => nearest_distances(tree, points, model),
+            Self::Walking(network) => network.nearest_distances(points),
+        }
+    }
+
+    /// The nearest station to every point and its distance
+    pub fn nearest_stations<'t>(
+        self,
+        tree: &'t StationTree,
+        points: &[PopulationPoint],
+    ) -> Vec<Option<(&'t StationRecord, f64)>> {
+        match self {
+            Self::Straight(model) => nearest_stations(tree, points, model),
+            Self::Walking(network) => {
+                let mut records = vec![None; tree.size()];
+                for station in tree.iter() {
+                    records[station.data.id] = Some(&station.data);
+                }
+                network
+                    .nearest_stations(points)
+                    .into_iter()
+                    .map(|nearest| {
+                        let (id, d) = nearest?;
+                        Some((records.get(id).copied().flatten()?, d))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// The number of stations within every threshold of every point,
+    /// indexed by threshold then by point
+    pub fn count_within_thresholds(
+        self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        thresholds: &[f64],
+    ) -> Vec<Vec<u32>> {
+        match self {
+            Self::Straight(model) => {
+                count_within_thresholds(tree, points, thresholds, model)
+            }
+            Self::Walking(network) => {
+                network.count_within_thresholds(points, thresholds)
+            }
+        }
+    }
+
+    /// The IDs of the stations within max_distance of every point, with
+    /// their distances
+    pub fn stations_within(
+        self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        max_distance: f64,
+    ) -> Vec<Vec<(usize, f64)>> {
+        match self {
+            Self::Straight(model) => {
+                stations_within(tree, points, max_distance, model)
+            }
+            Self::Walking(network) => {
+                network.stations_within(points, max_distance)
+            }
+        }
+    }
+
+    /// The sum of the station weights within every threshold of every
+    /// point, indexed by threshold then by point
+    pub fn sum_weights_within_thresholds(
+        self,
+        tree: &StationTree,
+        points: &[PopulationPoint],
+        thresholds: &[f64],
+    ) -> Vec<Vec<f64>> {
+        match self {
+            Self::Straight(model) => {
+                sum_weights_within_thresholds(tree, points, thresholds, model)
+            }
+            Self::Walking(network) => {
+                network.sum_weights_within_thresholds(points, thresholds)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Degrees of latitude per meter, on the haversine sphere
+    const DEG: f64 = 1.0 / 111_194.93;
+
+    fn point(lat_m: f64, lon: f64) -> PopulationPoint {
+        PopulationPoint {
+            lon,
+            lat: lat_m * DEG,
+            pop: 1.0,
+            x: 0.0,
+            y: 0.0,
+        }
+    }
+
+    fn station(lat_m: f64, lon: f64) -> Station {
+        Station {
+            name: "".into(),
+            lat: lat_m * DEG,
+            lon,
+            x: 0.0,
+            y: 0.0,
+            weight: 1.0,
+        }
+    }
+
+    /// A street up the meridian with nodes every 100 m, a parallel street
+    /// at 0.01° east that only joins it at the north end, and an isolated
+    /// footpath
+    fn network() -> Network {
+        let mut coords = HashMap::new();
+        for i in 0..=10 {
+            coords.insert(i, (0.0, i as f64 * 100.0 * DEG));
+            coords.insert(100 + i, (0.01, i as f64 * 100.0 * DEG));
+        }
+        coords.insert(200, (0.005, 0.0));
+        coords.insert(201, (0.005, 50.0 * DEG));
+        let ways = vec![
+            (0..=10).collect(),
+            (100..=110).collect(),
+            vec![10, 110],
+            vec![200, 201],
+            // cut off at the edge of the extract
+            vec![0, 999],
+        ];
+        let graph = WalkGraph::new(&coords, &ways);
+        assert_eq!(graph.len(), 22);
+        Network::new(graph, &[station(0.0, 0.0)])
+    }
+
+    #[test]
+    fn test_is_walkable() {
+        let tags = |kvs: &[(&str, &str)]| -> Tags {
+            kvs.iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        assert!(is_walkable(&tags(&[("highway", "footway")])));
+        assert!(!is_walkable(&tags(&[("highway", "motorway")])));
+        assert!(!is_walkable(&tags(&[("building", "yes")])));
+        assert!(!is_walkable(&tags(&[
+            ("highway", "primary"),
+            ("foot", "no")
+        ])));
+        assert!(!is_walkable(&tags(&[
+            ("highway", "service"),
+            ("access", "private")
+        ])));
+        assert!(is_walkable(&tags(&[
+            ("highway", "service"),
+            ("access", "private"),
+            ("foot", "yes")
+        ])));
+    }
+
+    #[test]
+    fn test_nearest_distances() {
+        let network = network();
+        let d = network.nearest_distances(&[
+            point(300.0, 0.0),
+            // snapped to the bottom of the parallel street, not the
+            // isolated footpath, so it walks up and back down
+            point(0.0, 0.0099),
+        ]);
+        assert!((d[0] - 300.0).abs() < 0.01, "{:?}", d);
+        let across = Haversine::distance(
+            Point::new(0.0, 1000.0 * DEG),
+            Point::new(0.01, 1000.0 * DEG),
+        );
+        let offset =
+            Haversine::distance(Point::new(0.0099, 0.0), Point::new(0.01, 0.0));
+        let expected = 2000.0 + across + offset;
+        assert!((d[1] - expected).abs() < 0.01, "{:?} {}", d, expected);
+    }
+
+    #[test]
+    fn test_nearest_stations() {
+        let graph = network().graph;
+        let stations = [station(0.0, 0.0), station(1000.0, 0.01)];
+        let network = Network::new(graph, &stations);
+        let nearest =
+            network.nearest_stations(&[point(300.0, 0.0), point(200.0, 0.01)]);
+        let ids: Vec<_> = nearest.iter().map(|n| n.unwrap().0).collect();
+        assert_eq!(ids, [0, 1]);
+        assert!((nearest[0].unwrap().1 - 300.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_count_within_thresholds() {
+        let graph = network().graph;
+        let stations = [
+            station(0.0, 0.0),
+            station(500.0, 0.0),
+            station(1000.0, 0.01),
+        ];
+        let network = Network::new(graph, &stations);
+        let points = [point(200.0, 0.0), point(900.0, 0.01)];
+        let counts =
+            network.count_within_thresholds(&points, &[250.0, 350.0, 3000.0]);
+        assert_eq!(counts, [vec![1, 1], vec![2, 1], vec![3, 3]]);
+
+        let within = network.stations_within(&points, 350.0);
+        assert_eq!(within[1].len(), 1);
+        assert_eq!(within[1][0].0, 2);
+        assert!((within[1][0].1 - 100.0).abs() < 0.01);
+        let mut first: Vec<_> = within[0].iter().map(|(id, _)| *id).collect();
+        first.sort();
+        assert_eq!(first, [0, 1]);
+    }
+
+    #[test]
+    fn test_sum_weights_within_thresholds() {
+        let graph = network().graph;
+        let mut stations = [station(0.0, 0.0), station(500.0, 0.0)];
+        stations[0].weight = 24.0;
+        stations[1].weight = 0.5;
+        let network = Network::new(graph, &stations);
+        let sums = network.sum_weights_within_thresholds(
+            &[point(200.0, 0.0)],
+            &[250.0, 350.0],
+        );
+        assert_eq!(sums, [vec![24.0], vec![24.5]]);
+    }
+}
@@ -0,0 +1,688 @@
+use flate2::read::ZlibDecoder;
+use quick_xml::events::{BytesStart, Event};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+/// The key-value tags of an OSM element
+pub type Tags = Vec<(String, String)>;
+
+/// The value of a tag, if the element has it
+pub fn tag<'a>(tags: &'a Tags, key: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsmNode {
+    pub id: i64,
+    pub lon: f64,
+    pub lat: f64,
+    pub tags: Tags,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsmWay {
+    pub id: i64,
+    /// The IDs of the nodes along the way, in order
+    pub refs: Vec<i64>,
+    pub tags: Tags,
+}
+
+/// The elements of an extract that the analyses use. Relations are skipped
+#[derive(Debug, Clone, PartialEq)]
+pub enum OsmElement {
+    Node(OsmNode),
+    Way(OsmWay),
+}
+
+/// Read a local OSM extract, calling f on every node and way in the order of
+/// the file. Extracts list every node before the ways.
+/// Files ending in .pbf are read as PBF, anything else as XML
+pub fn read_osm(
+    path: &str,
+    f: impl FnMut(OsmElement),
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file =
+        File::open(path).map_err(|e| format!("cannot read {}: {}", path, e))?;
+    let reader = BufReader::new(file);
+    let result = if path.ends_with(".pbf") {
+        read_osm_pbf(reader, f)
+    } else {
+        read_osm_xml(reader, f)
+    };
+    result.map_err(|e| format!("{}: {}", path, e).into())
+}
+
+fn attribute(e: &BytesStart, name: &str) -> Result<String, String> {
+    for attr in e.attributes() {
+        let attr = attr.map_err(|e| e.to_string())?;
+        if attr.key.as_ref() == name.as_bytes() {
+            let value = attr.unescape_value().map_err(|e| e.to_string())?;
+            return Ok(value.into_owned());
+        }
+    }
+    Err(format!(
+        "<{}> has no `{}`",
+        String::from_utf8_lossy(e.name().as_ref()),
+        name
+    ))
+}
+
+fn parse_attribute<T: std::str::FromStr>(
+    e: &BytesStart,
+    name: &str,
+) -> Result<T, String> {
+    let value = attribute(e, name)?;
+    value
+        .parse()
+        .map_err(|_| format!("cannot parse {} `{}`", name, value))
+}
+
+/// Read an .osm XML extract
+pub fn read_osm_xml<R: BufRead>(
+    reader: R,
+    mut f: impl FnMut(OsmElement),
+) -> Result<(), String> {
+    let mut reader = quick_xml::Reader::from_reader(reader);
+    let mut buf = vec![];
+    // the element whose tags and node refs are being read
+    let mut current: Option<OsmElement> = None;
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|e| e.to_string())?;
+        let (e, empty) = match &event {
+            Event::Start(e) => (e, false),
+            Event::Empty(e) => (e, true),
+            Event::End(e) => {
+                if matches!(e.name().as_ref(), b"node" | b"way") {
+                    if let Some(element) = current.take() {
+                        f(element);
+                    }
+                }
+                buf.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+
+        match e.name().as_ref() {
+            b"node" => {
+                current = Some(OsmElement::Node(OsmNode {
+                    id: parse_attribute(e, "id")?,
+                    lon: parse_attribute(e, "lon")?,
+                    lat: parse_attribute(e, "lat")?,
+                    tags: vec![],
+                }));
+            }
+            b"way" => {
+                current = Some(OsmElement::Way(OsmWay {
+                    id: parse_attribute(e, "id")?,
+                    refs: vec![],
+                    tags: vec![],
+                }));
+            }
+            b"tag" => {
+                let kv = (attribute(e, "k")?, attribute(e, "v")?);
+                match &mut current {
+                    Some(OsmElement::Node(n)) => n.tags.push(kv),
+                    Some(OsmElement::Way(w)) => w.tags.push(kv),
+                    None => {}
+                }
+            }
+            b"nd" => {
+                if let Some(OsmElement::Way(w)) = &mut current {
+                    w.refs.push(parse_attribute(e, "ref")?);
+                }
+            }
+            _ => {}
+        }
+        // <node .../> has no end tag
+        if empty && matches!(e.name().as_ref(), b"node" | b"way") {
+            if let Some(element) = current.take() {
+                f(element);
+            }
+        }
+        buf.clear();
+    }
+    Ok(())
+}
+
+/// The features that the PBF reader understands. Files that require others,
+/// such as historical information, are refused
+const PBF_FEATURES: [&str; 2] = ["OsmSchema-V0.6", "DenseNodes"];
+/// The largest BlobHeader the format allows
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+/// The largest Blob the format allows, compressed or not
+const MAX_BLOB_SIZE: usize = 32 * 1024 * 1024;
+
+/// A field of a protobuf message
+enum Value<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    /// fixed32 and fixed64, which no field used here has
+    Fixed,
+}
+
+/// A reader of the fields of a protobuf message
+struct Message<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Message<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn varint(&mut self) -> Result<u64, String> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.buf.get(self.pos).ok_or("truncated varint")?;
+            self.pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("varint is too long".into())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], String> {
+        let end = self.pos + len;
+        let bytes = self.buf.get(self.pos..end).ok_or("truncated field")?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// The number and value of the next field, or None at the end
+    fn field(&mut self) -> Result<Option<(u64, Value<'a>)>, String> {
+        if self.pos >= self.buf.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Value::Fixed
+            }
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed
+            }
+            t => return Err(format!("unknown wire type {}", t)),
+        };
+        Ok(Some((key >> 3, value)))
+    }
+}
+
+fn zigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+/// The varints of a packed repeated field
+fn packed(bytes: &[u8]) -> Result<Vec<u64>, String> {
+    let mut message = Message::new(bytes);
+    let mut values = vec![];
+    while message.pos < bytes.len() {
+        values.push(message.varint()?);
+    }
+    Ok(values)
+}
+
+/// The values of a packed, delta-coded sint64 field
+fn deltas(bytes: &[u8]) -> Result<Vec<i64>, String> {
+    let mut sum = 0;
+    Ok(packed(bytes)?
+        .into_iter()
+        .map(|n| {
+            sum += zigzag(n);
+            sum
+        })
+        .collect())
+}
+
+/// Read an .osm.pbf extract, which is a sequence of zlib-compressed blobs of
+/// protobuf messages
+pub fn read_osm_pbf<R: Read>(
+    mut reader: R,
+    mut f: impl FnMut(OsmElement),
+) -> Result<(), String> {
+    let mut len = [0; 4];
+    loop {
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.to_string()),
+        }
+        // the sizes come from the file, so they are checked before anything
+        // is allocated
+        let len = u32::from_be_bytes(len) as usize;
+        if len > MAX_HEADER_SIZE {
+            return Err(format!("blob header of {} bytes is too large", len));
+        }
+        let mut header = vec![0; len];
+        reader.read_exact(&mut header).map_err(|e| e.to_string())?;
+
+        let (mut kind, mut size) = (vec![], 0);
+        let mut message = Message::new(&header);
+        while let Some((field, value)) = message.field()? {
+            match (field, value) {
+                (1, Value::Bytes(b)) => kind = b.to_vec(),
+                (3, Value::Varint(n)) => size = n as usize,
+                _ => {}
+            }
+        }
+
+        if size > MAX_BLOB_SIZE {
+            return Err(format!("blob of {} bytes is too large", size));
+        }
+        let mut blob = vec![0; size];
+        reader.read_exact(&mut blob).map_err(|e| e.to_string())?;
+        let data = blob_data(&blob)?;
+        match &kind[..] {
+            b"OSMHeader" => check_features(&data)?,
+            b"OSMData" => read_block(&data, &mut f)?,
+            // unknown blobs are skipped, as the format allows
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The uncompressed contents of a blob, which can't be larger than its
+/// raw_size
+fn blob_data(blob: &[u8]) -> Result<Vec<u8>, String> {
+    let (mut raw_size, mut zlib) = (None, None);
+    let mut message = Message::new(blob);
+    while let Some((field, value)) = message.field()? {
+        match (field, value) {
+            (1, Value::Bytes(raw)) => return Ok(raw.to_vec()),
+            (2, Value::Varint(n)) => raw_size = Some(n as usize),
+            (3, Value::Bytes(data)) => zlib = Some(data),
+            (4..=7, _) => {
+                return Err("only zlib compressed blobs are supported".into())
+            }
+            _ => {}
+        }
+    }
+    let zlib = zlib.ok_or("blob has no data")?;
+    let raw_size = raw_size.ok_or("compressed blob has no raw_size")?;
+    if raw_size > MAX_BLOB_SIZE {
+        return Err(format!("blob of {} bytes is too large", raw_size));
+    }
+
+    // one byte more than raw_size tells that the data doesn't fit
+    let mut data = Vec::with_capacity(raw_size);
+    ZlibDecoder::new(zlib)
+        .take(raw_size as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| format!("cannot decompress blob: {}", e))?;
+    if data.len() > raw_size {
+        return Err(format!("blob is larger than its raw_size {}", raw_size));
+    }
+    Ok(data)
+}
+
+fn check_features(header: &[u8]) -> Result<(), String> {
+    let mut message = Message::new(header);
+    while let Some((field, value)) = message.field()? {
+        if let (4, Value::Bytes(feature)) = (field, value) {
+            let feature = String::from_utf8_lossy(feature);
+            if !PBF_FEATURES.contains(&feature.as_ref()) {
+                return Err(format!("unsupported feature {}", feature));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The strings and coordinate scaling of a block
+struct Block<'a> {
+    strings: Vec<&'a [u8]>,
+    granularity: i64,
+    lat_offset: i64,
+    lon_offset: i64,
+}
+
+impl Block<'_> {
+    fn string(&self, i: u64) -> Result<String, String> {
+        let s = self
+            .strings
+            .get(i as usize)
+            .ok_or_else(|| format!("string {} is not in the table", i))?;
+        Ok(String::from_utf8_lossy(s).into_owned())
+    }
+
+    fn tags(&self, keys: &[u64], vals: &[u64]) -> Result<Tags, String> {
+        keys.iter()
+            .zip(vals)
+            .map(|(k, v)| Ok((self.string(*k)?, self.string(*v)?)))
+            .collect()
+    }
+
+    fn degrees(&self, offset: i64, n: i64) -> f64 {
+        (offset + self.granularity * n) as f64 * 1e-9
+    }
+
+    fn lon(&self, n: i64) -> f64 {
+        self.degrees(self.lon_offset, n)
+    }
+
+    fn lat(&self, n: i64) -> f64 {
+        self.degrees(self.lat_offset, n)
+    }
+}
+
+fn read_block(
+    data: &[u8],
+    f: &mut impl FnMut(OsmElement),
+) -> Result<(), String> {
+    let mut block = Block {
+        strings: vec![],
+        granularity: 100,
+        lat_offset: 0,
+        lon_offset: 0,
+    };
+    let mut groups = vec![];
+    let mut message = Message::new(data);
+    while let Some((field, value)) = message.field()? {
+        match (field, value) {
+            (1, Value::Bytes(table)) => {
+                let mut table = Message::new(table);
+                while let Some((field, value)) = table.field()? {
+                    if let (1, Value::Bytes(s)) = (field, value) {
+                        block.strings.push(s);
+                    }
+                }
+            }
+            (2, Value::Bytes(group)) => groups.push(group),
+            (17, Value::Varint(n)) => block.granularity = n as i64,
+            (19, Value::Varint(n)) => block.lat_offset = n as i64,
+            (20, Value::Varint(n)) => block.lon_offset = n as i64,
+            _ => {}
+        }
+    }
+
+    for group in groups {
+        let mut group = Message::new(group);
+        while let Some((field, value)) = group.field()? {
+            match (field, value) {
+                (1, Value::Bytes(node)) => f(read_node(&block, node)?),
+                (2, Value::Bytes(dense)) => read_dense(&block, dense, f)?,
+                (3, Value::Bytes(way)) => f(read_way(&block, way)?),
+                _ => {}
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_node(block: &Block, node: &[u8]) -> Result<OsmElement, String> {
+    let (mut id, mut lat, mut lon) = (0, 0, 0);
+    let (mut keys, mut vals) = (vec![], vec![]);
+    let mut message = Message::new(node);
+    while let Some((field, value)) = message.field()? {
+        match (field, value) {
+            (1, Value::Varint(n)) => id = zigzag(n),
+            (2, Value::Bytes(b)) => keys = packed(b)?,
+            (3, Value::Bytes(b)) => vals = packed(b)?,
+            (8, Value::Varint(n)) => lat = zigzag(n),
+            (9, Value::Varint(n)) => lon = zigzag(n),
+            _ => {}
+        }
+    }
+    Ok(OsmElement::Node(OsmNode {
+        id,
+        lon: block.lon(lon),
+        lat: block.lat(lat),
+        tags: block.tags(&keys, &vals)?,
+    }))
+}
+
+fn read_dense(
+    block: &Block,
+    dense: &[u8],
+    f: &mut impl FnMut(OsmElement),
+) -> Result<(), String> {
+    let (mut ids, mut lats, mut lons) = (vec![], vec![], vec![]);
+    let mut keys_vals = vec![];
+    let mut message = Message::new(dense);
+    while let Some((field, value)) = message.field()? {
+        match (field, value) {
+            (1, Value::Bytes(b)) => ids = deltas(b)?,
+            (8, Value::Bytes(b)) => lats = deltas(b)?,
+            (9, Value::Bytes(b)) => lons = deltas(b)?,
+            (10, Value::Bytes(b)) => keys_vals = packed(b)?,
+            _ => {}
+        }
+    }
+    if lats.len() != ids.len() || lons.len() != ids.len() {
+        return Err("dense nodes have mismatched ids and coordinates".into());
+    }
+
+    // the tags of every node, as key, value pairs ending in a 0
+    let mut keys_vals = keys_vals.into_iter();
+    for ((id, lat), lon) in ids.into_iter().zip(lats).zip(lons) {
+        let mut tags = vec![];
+        while let Some(key) = keys_vals.next() {
+            if key == 0 {
+                break;
+            }
+            let val = keys_vals.next().ok_or("tag has no value")?;
+            tags.push((block.string(key)?, block.string(val)?));
+        }
+        f(OsmElement::Node(OsmNode {
+            id,
+            lon: block.lon(lon),
+            lat: block.lat(lat),
+            tags,
+        }));
+    }
+    Ok(())
+}
+
+fn read_way(block: &Block, way: &[u8]) -> Result<OsmElement, String> {
+    let mut id = 0;
+    let (mut keys, mut vals, mut refs) = (vec![], vec![], vec![]);
+    let mut message = Message::new(way);
+    while let Some((field, value)) = message.field()? {
+        match (field, value) {
+            (1, Value::Varint(n)) => id = n as i64,
+            (2, Value::Bytes(b)) => keys = packed(b)?,
+            (3, Value::Bytes(b)) => vals = packed(b)?,
+            (8, Value::Bytes(b)) => refs = deltas(b)?,
+            _ => {}
+        }
+    }
+    Ok(OsmElement::Way(OsmWay {
+        id,
+        refs,
+        tags: block.tags(&keys, &vals)?,
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn elements_xml(xml: &str) -> Vec<OsmElement> {
+        let mut elements = vec![];
+        read_osm_xml(xml.as_bytes(), |e| elements.push(e)).unwrap();
+        elements
+    }
+
+    #[test]
+    fn test_read_xml() {
+        let elements = elements_xml(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+            <osm version="0.6">
+              <node id="1" lat="51.5" lon="-0.1"/>
+              <node id="2" lat="51.6" lon="-0.2">
+                <tag k="railway" v="station"/>
+                <tag k="name" v="Bank &amp; Monument"/>
+              </node>
+              <way id="10">
+                <nd ref="1"/>
+                <nd ref="2"/>
+                <tag k="highway" v="footway"/>
+              </way>
+              <relation id="100"><member type="way" ref="10"/></relation>
+            </osm>"#,
+        );
+        assert_eq!(elements.len(), 3);
+        let OsmElement::Node(station) = &elements[1] else {
+            panic!("{:?}", elements[1]);
+        };
+        assert_eq!((station.lon, station.lat), (-0.2, 51.6));
+        assert_eq!(tag(&station.tags, "name"), Some("Bank & Monument"));
+        let OsmElement::Way(way) = &elements[2] else {
+            panic!("{:?}", elements[2]);
+        };
+        assert_eq!(way.refs, [1, 2]);
+        assert_eq!(tag(&way.tags, "highway"), Some("footway"));
+    }
+
+    fn varint(mut n: u64, out: &mut Vec<u8>) {
+        while n >= 0x80 {
+            out.push(n as u8 | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+
+    fn bytes_field(field: u64, bytes: &[u8], out: &mut Vec<u8>) {
+        varint(field << 3 | 2, out);
+        varint(bytes.len() as u64, out);
+        out.extend(bytes);
+    }
+
+    fn varint_field(field: u64, n: u64, out: &mut Vec<u8>) {
+        varint(field << 3, out);
+        varint(n, out);
+    }
+
+    fn packed_field(field: u64, values: &[i64], zigzag: bool) -> Vec<u8> {
+        let mut bytes = vec![];
+        for v in values {
+            let n = if zigzag {
+                ((v << 1) ^ (v >> 63)) as u64
+            } else {
+                *v as u64
+            };
+            varint(n, &mut bytes);
+        }
+        let mut out = vec![];
+        bytes_field(field, &bytes, &mut out);
+        out
+    }
+
+    fn blob(kind: &str, data: &[u8], out: &mut Vec<u8>) {
+        let mut zlib = ZlibEncoder::new(vec![], Default::default());
+        zlib.write_all(data).unwrap();
+        let mut blob = vec![];
+        varint_field(2, data.len() as u64, &mut blob);
+        bytes_field(3, &zlib.finish().unwrap(), &mut blob);
+
+        let mut header = vec![];
+        bytes_field(1, kind.as_bytes(), &mut header);
+        varint_field(3, blob.len() as u64, &mut header);
+        out.extend((header.len() as u32).to_be_bytes());
+        out.extend(header);
+        out.extend(blob);
+    }
+
+    #[test]
+    fn test_read_pbf() {
+        let mut table = vec![];
+        for s in ["", "railway", "station", "highway", "footway"] {
+            bytes_field(1, s.as_bytes(), &mut table);
+        }
+
+        // nodes 5 and 7, at (0.1, 51.5) and (-0.2, 51.25), deltas coded
+        let mut dense = packed_field(1, &[5, 2], true);
+        dense.extend(packed_field(8, &[515_000_000, -2_500_000], true));
+        dense.extend(packed_field(9, &[1_000_000, -3_000_000], true));
+        dense.extend(packed_field(10, &[0, 1, 2, 0], false));
+
+        let mut way = vec![];
+        varint_field(1, 9, &mut way);
+        way.extend(packed_field(2, &[3], false));
+        way.extend(packed_field(3, &[4], false));
+        way.extend(packed_field(8, &[5, 2], true));
+
+        let mut group = vec![];
+        bytes_field(2, &dense, &mut group);
+        bytes_field(3, &way, &mut group);
+
+        let mut block = vec![];
+        bytes_field(1, &table, &mut block);
+        bytes_field(2, &group, &mut block);
+
+        let mut header = vec![];
+        bytes_field(4, b"OsmSchema-V0.6", &mut header);
+        bytes_field(4, b"DenseNodes", &mut header);
+
+        let mut file = vec![];
+        blob("OSMHeader", &header, &mut file);
+        blob("OSMData", &block, &mut file);
+
+        let mut elements = vec![];
+        read_osm_pbf(&file[..], |e| elements.push(e)).unwrap();
+        let [OsmElement::Node(a), OsmElement::Node(b), OsmElement::Way(w)] =
+            &elements[..]
+        else {
+            panic!("{:?}", elements);
+        };
+        assert_eq!(a.id, 5);
+        assert!(a.tags.is_empty());
+        assert!((a.lat - 51.5).abs() < 1e-9 && (a.lon - 0.1).abs() < 1e-9);
+        assert_eq!(b.id, 7);
+        assert!((b.lat - 51.25).abs() < 1e-9 && (b.lon + 0.2).abs() < 1e-9);
+        assert_eq!(tag(&b.tags, "railway"), Some("station"));
+        assert_eq!(w.refs, [5, 7]);
+        assert_eq!(tag(&w.tags, "highway"), Some("footway"));
+
+        let mut header = vec![];
+        bytes_field(4, b"HistoricalInformation", &mut header);
+        let mut file = vec![];
+        blob("OSMHeader", &header, &mut file);
+        let err = read_osm_pbf(&file[..], |_| {}).unwrap_err();
+        assert_eq!(err, "unsupported feature HistoricalInformation");
+    }
+
+    #[test]
+    fn test_pbf_limits() {
+        // a header length beyond 64 KiB is rejected before it is read
+        let file = u32::MAX.to_be_bytes();
+        let err = read_osm_pbf(&file[..], |_| {}).unwrap_err();
+        assert_eq!(err, "blob header of 4294967295 bytes is too large");
+
+        let mut header = vec![];
+        bytes_field(1, b"OSMData", &mut header);
+        varint_field(3, 1 << 40, &mut header);
+        let mut file = (header.len() as u32).to_be_bytes().to_vec();
+        file.extend(header);
+        let err = read_osm_pbf(&file[..], |_| {}).unwrap_err();
+        assert_eq!(err, "blob of 1099511627776 bytes is too large");
+
+        // the data inflates past the raw_size it claims
+        let mut zlib = ZlibEncoder::new(vec![], Default::default());
+        zlib.write_all(&[0; 100]).unwrap();
+        let mut blob = vec![];
+        varint_field(2, 10, &mut blob);
+        bytes_field(3, &zlib.finish().unwrap(), &mut blob);
+        let err = blob_data(&blob).unwrap_err();
+        assert_eq!(err, "blob is larger than its raw_size 10");
+    }
+}
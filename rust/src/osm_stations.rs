@@ -0,0 +1,448 @@
+use crate::osm::{read_osm, tag, OsmElement, Tags};
+use geo::{Distance, Haversine, Point};
+use rstar::primitives::GeomWithData;
+use rstar::{RTree, AABB};
+use std::collections::HashMap;
+
+/// Elements with the same name within this many meters are the same
+/// station, such as the node of every operator at an interchange, or the
+/// node and the area of a station
+const SAME_STATION: f64 = 300.0;
+
+/// Unnamed elements this close to another element are part of its station
+const UNNAMED_STATION: f64 = 100.0;
+
+/// Entrances this close to a station are named after it
+const ENTRANCE_OF: f64 = 500.0;
+
+/// Meters in a degree of latitude, to turn the distances above into boxes of
+/// lon/lat for the tree
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// What is read from the extract
+#[derive(Debug, Clone)]
+pub struct StationOptions {
+    /// Also output every railway=subway_entrance, named after the nearest
+    /// station
+    pub entrances: bool,
+    /// The tag to name the stations from, such as name:en
+    pub name_tag: String,
+}
+
+impl Default for StationOptions {
+    fn default() -> Self {
+        Self {
+            entrances: false,
+            name_tag: "name".into(),
+        }
+    }
+}
+
+/// A station or entrance read from the extract, before reprojection
+#[derive(Debug, Clone, PartialEq)]
+pub struct OsmStation {
+    pub name: String,
+    pub lon: f64,
+    pub lat: f64,
+}
+
+/// Whether an element is a station. Miniature railways are left out
+pub fn is_station(tags: &Tags) -> bool {
+    let railway = tag(tags, "railway");
+    let station = railway == Some("station")
+        || tag(tags, "public_transport") == Some("station");
+    station && tag(tags, "station") != Some("miniature")
+}
+
+/// Whether a node is where trains stop, which is merged into its station.
+/// The stop positions of buses are left out
+fn is_stop_position(tags: &Tags) -> bool {
+    let rail = ["train", "subway", "light_rail", "monorail"]
+        .iter()
+        .any(|mode| tag(tags, mode) == Some("yes"));
+    tag(tags, "railway") == Some("stop")
+        || (tag(tags, "public_transport") == Some("stop_position") && rail)
+}
+
+fn is_entrance(tags: &Tags) -> bool {
+    tag(tags, "railway") == Some("subway_entrance")
+}
+
+/// An element that is part of a station
+struct Candidate {
+    name: Option<String>,
+    /// The OSM element, to name unnamed stations
+    element: String,
+    /// A stop position, which only joins a station and is never one
+    stop: bool,
+    lon: f64,
+    lat: f64,
+}
+
+/// Read the stations, and the entrances if asked, from a local .osm.pbf or
+/// .osm extract. Station areas are placed at the centroid of their nodes,
+/// and the stop positions of trains are merged into the nearest station.
+/// The stations are sorted by name, then the entrances
+pub fn extract_stations(
+    path: &str,
+    options: &StationOptions,
+) -> Result<Vec<OsmStation>, Box<dyn std::error::Error>> {
+    // the node coordinates are kept for the station areas, which come after
+    // all nodes
+    let mut coords = HashMap::new();
+    let mut candidates = vec![];
+    let mut entrances = vec![];
+    read_osm(path, |element| match element {
+        OsmElement::Node(n) => {
+            let stop = is_stop_position(&n.tags);
+            if is_station(&n.tags) || stop {
+                candidates.push(Candidate {
+                    name: tag(&n.tags, &options.name_tag).map(String::from),
+                    element: format!("node/{}", n.id),
+                    stop: !is_station(&n.tags) && stop,
+                    lon: n.lon,
+                    lat: n.lat,
+                });
+            } else if options.entrances && is_entrance(&n.tags) {
+                entrances.push((n.lon, n.lat));
+            }
+            coords.insert(n.id, (n.lon, n.lat));
+        }
+        OsmElement::Way(w) if is_station(&w.tags) => {
+            // a closed way repeats its first node at the end, which would
+            // count twice
+            let refs = match &w.refs[..] {
+                [first, .., last] if first == last => {
+                    &w.refs[..w.refs.len() - 1]
+                }
+                refs => refs,
+            };
+            let nodes: Vec<_> =
+                refs.iter().filter_map(|id| coords.get(id)).collect();
+            if nodes.is_empty() {
+                return;
+            }
+            let n = nodes.len() as f64;
+            candidates.push(Candidate {
+                name: tag(&w.tags, &options.name_tag).map(String::from),
+                element: format!("way/{}", w.id),
+                stop: false,
+                lon: nodes.iter().map(|(lon, _)| lon).sum::<f64>() / n,
+                lat: nodes.iter().map(|(_, lat)| lat).sum::<f64>() / n,
+            });
+        }
+        OsmElement::Way(_) => {}
+    })?;
+
+    let mut stations = merge_candidates(candidates);
+    if options.entrances {
+        let named = name_entrances(&stations, &entrances);
+        stations.extend(named);
+    }
+    Ok(stations)
+}
+
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    Haversine::distance(Point::from(a), Point::from(b))
+}
+
+/// Elements in a tree of their lon/lat, to find the nearest one within a
+/// distance without comparing every pair
+struct Nearby {
+    tree: RTree<GeomWithData<[f64; 2], usize>>,
+}
+
+impl Nearby {
+    fn new(positions: impl Iterator<Item = (f64, f64)>) -> Self {
+        let points = positions
+            .enumerate()
+            .map(|(i, (lon, lat))| GeomWithData::new([lon, lat], i))
+            .collect();
+        Self {
+            tree: RTree::bulk_load(points),
+        }
+    }
+
+    /// The nearest element that is kept, within max_distance meters
+    fn nearest(
+        &self,
+        (lon, lat): (f64, f64),
+        max_distance: f64,
+        keep: impl Fn(usize) -> bool,
+    ) -> Option<(usize, f64)> {
+        // a box that holds the circle: the degrees of longitude shrink
+        // towards the poles
+        let dlat = max_distance / METERS_PER_DEGREE;
+        let dlon = dlat / lat.to_radians().cos().max(0.01);
+        let envelope = AABB::from_corners(
+            [lon - dlon, lat - dlat],
+            [lon + dlon, lat + dlat],
+        );
+        self.tree
+            .locate_in_envelope(&envelope)
+            .filter(|p| keep(p.data))
+            .map(|p| (p.data, distance((lon, lat), (p.geom()[0], p.geom()[1]))))
+            .filter(|(_, d)| *d <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+}
+
+fn find(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Merge the elements of the same station into one, at their mean position
+fn merge_candidates(candidates: Vec<Candidate>) -> Vec<OsmStation> {
+    let position = |c: &Candidate| (c.lon, c.lat);
+
+    // named elements are grouped by their name, ignoring case
+    let mut by_name: HashMap<String, Vec<usize>> = HashMap::new();
+    for (i, c) in candidates.iter().enumerate() {
+        if let Some(name) = &c.name {
+            by_name
+                .entry(name.trim().to_lowercase())
+                .or_default()
+                .push(i);
+        }
+    }
+    let mut parents: Vec<_> = (0..candidates.len()).collect();
+    for group in by_name.values() {
+        for (j, a) in group.iter().enumerate() {
+            for b in &group[j + 1..] {
+                let (pa, pb) =
+                    (position(&candidates[*a]), position(&candidates[*b]));
+                if distance(pa, pb) <= SAME_STATION {
+                    let (ra, rb) =
+                        (find(&mut parents, *a), find(&mut parents, *b));
+                    parents[ra] = rb;
+                }
+            }
+        }
+    }
+
+    // unnamed elements join the nearest other element if it is close
+    // enough, which is also how unnamed elements are merged together. Stop
+    // positions join the nearest station element, named or not
+    let nearby = Nearby::new(candidates.iter().map(position));
+    let is_element = |j: usize| !candidates[j].stop;
+    for (i, c) in candidates.iter().enumerate() {
+        let nearest = if c.stop {
+            nearby.nearest(position(c), SAME_STATION, is_element)
+        } else if c.name.is_none() {
+            nearby.nearest(position(c), UNNAMED_STATION, |j| {
+                j != i && is_element(j)
+            })
+        } else {
+            None
+        };
+        if let Some((j, _)) = nearest {
+            let (ri, rj) = (find(&mut parents, i), find(&mut parents, j));
+            parents[ri] = rj;
+        }
+    }
+
+    // the stop positions only lend their names, so the stations stay where
+    // their elements are, and the stops without a station are dropped
+    let mut groups: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..candidates.len() {
+        groups.entry(find(&mut parents, i)).or_default().push(i);
+    }
+    let mut stations: Vec<_> = groups
+        .into_values()
+        .filter_map(|members| {
+            let (elements, stops): (Vec<_>, Vec<_>) =
+                members.into_iter().partition(|i| is_element(*i));
+            let n = elements.len() as f64;
+            let first_named = elements
+                .iter()
+                .chain(&stops)
+                .find_map(|i| candidates[*i].name.clone());
+            let first = elements.iter().min()?;
+            Some(OsmStation {
+                name: first_named
+                    .unwrap_or_else(|| candidates[*first].element.clone()),
+                lon: elements.iter().map(|i| candidates[*i].lon).sum::<f64>()
+                    / n,
+                lat: elements.iter().map(|i| candidates[*i].lat).sum::<f64>()
+                    / n,
+            })
+        })
+        .collect();
+    stations.sort_by(|a, b| {
+        a.name
+            .cmp(&b.name)
+            .then(a.lon.total_cmp(&b.lon))
+            .then(a.lat.total_cmp(&b.lat))
+    });
+    stations
+}
+
+/// Name every entrance after the nearest station, or `entrance` if there is
+/// none close enough
+fn name_entrances(
+    stations: &[OsmStation],
+    entrances: &[(f64, f64)],
+) -> Vec<OsmStation> {
+    let nearby = Nearby::new(stations.iter().map(|s| (s.lon, s.lat)));
+    entrances
+        .iter()
+        .map(|(lon, lat)| {
+            let nearest = nearby.nearest((*lon, *lat), ENTRANCE_OF, |_| true);
+            let name = match nearest {
+                Some((i, _)) => format!("{} entrance", stations[i].name),
+                None => "entrance".into(),
+            };
+            OsmStation {
+                name,
+                lon: *lon,
+                lat: *lat,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    const OSM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="51.5130" lon="-0.0890">
+            <tag k="railway" v="station"/>
+            <tag k="name" v="Bank"/>
+            <tag k="network" v="London Underground"/>
+          </node>
+          <node id="2" lat="51.5132" lon="-0.0886">
+            <tag k="railway" v="station"/>
+            <tag k="public_transport" v="station"/>
+            <tag k="name" v="BANK"/>
+            <tag k="network" v="Docklands Light Railway"/>
+          </node>
+          <node id="3" lat="51.5134" lon="-0.0888">
+            <tag k="public_transport" v="station"/>
+          </node>
+          <node id="4" lat="51.5200" lon="-0.1000">
+            <tag k="railway" v="station"/>
+            <tag k="station" v="miniature"/>
+            <tag k="name" v="Toy Town"/>
+          </node>
+          <node id="5" lat="51.5150" lon="-0.0700"/>
+          <node id="6" lat="51.5150" lon="-0.0720"/>
+          <node id="7" lat="51.5170" lon="-0.0720"/>
+          <node id="8" lat="51.5135" lon="-0.0889">
+            <tag k="railway" v="subway_entrance"/>
+          </node>
+          <node id="9" lat="51.5135" lon="-0.0900">
+            <tag k="railway" v="platform"/>
+            <tag k="name" v="Bank"/>
+          </node>
+          <way id="10">
+            <nd ref="5"/><nd ref="6"/><nd ref="7"/>
+            <tag k="public_transport" v="station"/>
+            <tag k="name" v="Aldgate East"/>
+            <tag k="name:ja" v="オールドゲート・イースト"/>
+          </way>
+        </osm>"#;
+
+    fn extract(
+        name: &str,
+        osm: &str,
+        options: &StationOptions,
+    ) -> Vec<OsmStation> {
+        let dir = std::env::temp_dir().join("atupp_osm_stations_test");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, osm).unwrap();
+        extract_stations(path.to_str().unwrap(), options).unwrap()
+    }
+
+    #[test]
+    fn test_extract_stations() {
+        let stations = extract("stations.osm", OSM, &StationOptions::default());
+        let names: Vec<_> = stations.iter().map(|s| &s.name).collect();
+        assert_eq!(names, ["Aldgate East", "Bank"]);
+        assert!((stations[0].lat - 51.515667).abs() < 1e-6);
+        assert!((stations[0].lon + 0.071333).abs() < 1e-6);
+        // the unnamed station node is merged into bank too
+        assert!((stations[1].lat - 51.5132).abs() < 1e-9);
+        assert!((stations[1].lon + 0.0888).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_entrances_and_name_tag() {
+        let options = StationOptions {
+            entrances: true,
+            name_tag: "name:ja".into(),
+        };
+        let stations = extract("entrances.osm", OSM, &options);
+        let names: Vec<_> = stations.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(
+            names,
+            ["node/1", "オールドゲート・イースト", "node/1 entrance"]
+        );
+        assert_eq!(stations[2].lat, 51.5135);
+    }
+
+    #[test]
+    fn test_closed_way_centroid() {
+        let osm = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="51.0" lon="0.0"/>
+          <node id="2" lat="51.0" lon="0.002"/>
+          <node id="3" lat="51.002" lon="0.002"/>
+          <node id="4" lat="51.002" lon="0.0"/>
+          <way id="5">
+            <nd ref="1"/><nd ref="2"/><nd ref="3"/><nd ref="4"/><nd ref="1"/>
+            <tag k="railway" v="station"/>
+            <tag k="name" v="Square"/>
+          </way>
+        </osm>"#;
+        let stations = extract("square.osm", osm, &StationOptions::default());
+        assert!((stations[0].lat - 51.001).abs() < 1e-9);
+        assert!((stations[0].lon - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_stop_positions() {
+        let osm = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <node id="1" lat="51.48" lon="-0.11">
+            <tag k="railway" v="station"/>
+            <tag k="name" v="Oval"/>
+          </node>
+          <node id="2" lat="51.4805" lon="-0.1105">
+            <tag k="public_transport" v="stop_position"/>
+            <tag k="subway" v="yes"/>
+            <tag k="name" v="Oval"/>
+          </node>
+          <node id="3" lat="51.4795" lon="-0.1095">
+            <tag k="railway" v="stop"/>
+          </node>
+          <node id="4" lat="51.4801" lon="-0.1101">
+            <tag k="public_transport" v="stop_position"/>
+            <tag k="bus" v="yes"/>
+            <tag k="name" v="Oval Bus"/>
+          </node>
+          <node id="5" lat="51.60" lon="-0.30">
+            <tag k="railway" v="stop"/>
+            <tag k="name" v="Nowhere"/>
+          </node>
+        </osm>"#;
+        let stations = extract("stops.osm", osm, &StationOptions::default());
+        // the stops are merged into oval, which stays where its node is, the
+        // bus stop is ignored and the stop without a station is dropped
+        assert_eq!(
+            stations,
+            [OsmStation {
+                name: "Oval".into(),
+                lon: -0.11,
+                lat: 51.48,
+            }]
+        );
+    }
+}
@@ -1,24 +1,60 @@
-use plotters::coord::types::{RangedCoordf64, RangedCoordi32};
+use plotters::coord::ranged1d::Ranged;
+use plotters::coord::types::RangedCoordf64;
 use plotters::coord::Shift;
 use plotters::prelude::*;
+use std::path::Path;
 
 // adapted from my previous project train-passenger-distribution
-pub type Chart<'a, 'b> = ChartContext<
-    'a,
-    BitMapBackend<'b>,
-    Cartesian2d<RangedCoordf64, RangedCoordi32>,
->;
-
-pub fn plot_vline(
-    root: &DrawingArea<BitMapBackend, Shift>,
-    chart: &Chart,
-    x_value: f64,
+pub type Chart<'a, DB, X = RangedCoordf64, Y = RangedCoordf64> =
+    ChartContext<'a, DB, Cartesian2d<X, Y>>;
+
+/// What a plot is rendered into, from the extension of its output file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// .svg, for publications
+    Svg,
+    /// .rgb, the raw pixels row by row, 3 bytes each, for embedding
+    Rgb,
+    /// Anything else, such as .png, for quick looks
+    Bitmap,
+}
+
+impl Format {
+    pub fn of(path: &str) -> Self {
+        let extension = Path::new(path)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("svg") => Self::Svg,
+            Some("rgb") => Self::Rgb,
+            _ => Self::Bitmap,
+        }
+    }
+}
+
+/// A black buffer of RGB pixels of the size, for BitMapBackend::with_buffer
+pub fn rgb_buffer((width, height): (u32, u32)) -> Vec<u8> {
+    vec![0; width as usize * height as usize * 3]
+}
+
+/// Draw a vertical line from the bottom of the chart at x_value, up to the
+/// pixel row top_y of the root
+pub fn plot_vline<DB, X, Y>(
+    root: &DrawingArea<DB, Shift>,
+    chart: &Chart<DB, X, Y>,
+    x_value: X::ValueType,
     modifier: i32,
     top_y: i32,
     stroke: ShapeStyle,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    X: Ranged,
+    Y: Ranged,
+{
     let drawing_area = chart.plotting_area();
-    let mapped = drawing_area.map_coordinate(&(x_value, 0));
+    let mapped = drawing_area.map_coordinate(&(x_value, chart.y_range().start));
     let p: PathElement<(i32, i32)> = PathElement::new(
         [(mapped.0, mapped.1 - modifier), (mapped.0, top_y)],
         stroke,
@@ -27,16 +63,25 @@ pub fn plot_vline(
     Ok(())
 }
 
-pub fn plot_hline(
-    root: &DrawingArea<BitMapBackend, Shift>,
-    chart: &Chart,
-    y_value: i32,
+/// Draw a horizontal line from the left of the chart at y_value, to left_x
+pub fn plot_hline<DB, X, Y>(
+    root: &DrawingArea<DB, Shift>,
+    chart: &Chart<DB, X, Y>,
+    y_value: Y::ValueType,
     modifier: i32,
-    left_x: f64,
+    left_x: X::ValueType,
     stroke: ShapeStyle,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    DB: DrawingBackend,
+    DB::ErrorType: 'static,
+    X: Ranged,
+    Y: Ranged,
+    Y::ValueType: Clone,
+{
     let drawing_area = chart.plotting_area();
-    let mapped = drawing_area.map_coordinate(&(0., y_value));
+    let mapped =
+        drawing_area.map_coordinate(&(chart.x_range().start, y_value.clone()));
     let end = drawing_area.map_coordinate(&(left_x, y_value));
     let p: PathElement<(i32, i32)> = PathElement::new(
         [(mapped.0, mapped.1 - modifier), (end.0, end.1)],
@@ -45,3 +90,37 @@ pub fn plot_hline(
     root.draw(&p)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Format::of("../out/props.svg"), Format::Svg);
+        assert_eq!(Format::of("../out/props.SVG"), Format::Svg);
+        assert_eq!(Format::of("../out/props.rgb"), Format::Rgb);
+        assert_eq!(Format::of("../out/props.png"), Format::Bitmap);
+        assert_eq!(Format::of("../out/props"), Format::Bitmap);
+    }
+
+    #[test]
+    fn test_lines_on_any_backend() {
+        let mut buffer = rgb_buffer((100, 100));
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, (100, 100))
+                .into_drawing_area();
+            root.fill(&WHITE).unwrap();
+            let chart = ChartBuilder::on(&root)
+                .build_cartesian_2d(0..10, 0.0..1.0)
+                .unwrap();
+            plot_vline(&root, &chart, 5, 0, 0, BLACK.into()).unwrap();
+            plot_hline(&root, &chart, 0.5, 0, 10, BLACK.into()).unwrap();
+            root.present().unwrap();
+        }
+        // the middle pixel is on both lines
+        let middle = (50 * 100 + 50) * 3;
+        assert_eq!(&buffer[middle..middle + 3], [0, 0, 0]);
+        assert_eq!(&buffer[0..3], [255, 255, 255]);
+    }
+}
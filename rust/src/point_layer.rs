@@ -0,0 +1,168 @@
+use geojson::{Feature, FeatureCollection, Geometry, JsonObject, JsonValue};
+use std::fs::{self, File};
+use std::io::Write;
+
+/// A value in a column of a point layer
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerValue {
+    Integer(usize),
+    Number(f64),
+    Text(String),
+}
+
+impl From<usize> for LayerValue {
+    fn from(value: usize) -> Self {
+        Self::Integer(value)
+    }
+}
+
+impl From<f64> for LayerValue {
+    fn from(value: f64) -> Self {
+        Self::Number(value)
+    }
+}
+
+impl From<String> for LayerValue {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for LayerValue {
+    fn from(value: &str) -> Self {
+        Self::Text(value.into())
+    }
+}
+
+impl LayerValue {
+    fn to_csv(&self) -> String {
+        match self {
+            Self::Integer(i) => i.to_string(),
+            Self::Number(n) => n.to_string(),
+            Self::Text(s) => s.clone(),
+        }
+    }
+
+    fn to_json(&self) -> JsonValue {
+        match self {
+            Self::Integer(i) => JsonValue::from(*i),
+            Self::Number(n) => JsonValue::from(*n),
+            Self::Text(s) => JsonValue::from(s.as_str()),
+        }
+    }
+}
+
+/// A point in lat/lon, with a value for every column of its layer
+#[derive(Debug, Clone, PartialEq)]
+pub struct PointRow {
+    pub lon: f64,
+    pub lat: f64,
+    pub values: Vec<LayerValue>,
+}
+
+pub fn write_point_layer<W: Write>(
+    writer: W,
+    columns: &[&str],
+    rows: &[PointRow],
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(columns)?;
+    for row in rows {
+        writer.write_record(row.values.iter().map(LayerValue::to_csv))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A point feature for every row, with the columns of the csv as its
+/// properties
+pub fn point_layer_geojson(
+    columns: &[&str],
+    rows: &[PointRow],
+) -> FeatureCollection {
+    let features = rows
+        .iter()
+        .map(|row| {
+            let properties: JsonObject = columns
+                .iter()
+                .map(|column| column.to_string())
+                .zip(row.values.iter().map(LayerValue::to_json))
+                .collect();
+            Feature {
+                bbox: None,
+                geometry: Some(Geometry::new(geojson::Value::Point(vec![
+                    row.lon, row.lat,
+                ]))),
+                id: None,
+                properties: Some(properties),
+                foreign_members: None,
+            }
+        })
+        .collect();
+    FeatureCollection {
+        bbox: None,
+        features,
+        foreign_members: None,
+    }
+}
+
+/// Write the rows as a csv to path, and as GeoJSON next to it with the
+/// .geojson extension. Returns the path of the GeoJSON
+pub fn save_point_layer(
+    path: &str,
+    columns: &[&str],
+    rows: &[PointRow],
+) -> Result<String, Box<dyn std::error::Error>> {
+    let file = File::create(path)
+        .map_err(|e| format!("cannot write {}: {}", path, e))?;
+    write_point_layer(file, columns, rows)
+        .map_err(|e| format!("{}: {}", path, e))?;
+
+    let geojson_path = std::path::Path::new(path)
+        .with_extension("geojson")
+        .to_string_lossy()
+        .into_owned();
+    fs::write(
+        &geojson_path,
+        point_layer_geojson(columns, rows).to_string(),
+    )
+    .map_err(|e| format!("cannot write {}: {}", geojson_path, e))?;
+    Ok(geojson_path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rows() -> Vec<PointRow> {
+        vec![PointRow {
+            lon: -0.1,
+            lat: 51.5,
+            values: vec![0.into(), "Bank, City".into(), 2.0.into()],
+        }]
+    }
+
+    #[test]
+    fn test_write_point_layer() {
+        let mut csv = vec![];
+        write_point_layer(&mut csv, &["id", "name", "pop"], &rows()).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "id,name,pop\n0,\"Bank, City\",2\n"
+        );
+    }
+
+    #[test]
+    fn test_point_layer_geojson() {
+        let geojson = point_layer_geojson(&["id", "name", "pop"], &rows());
+        let feature = &geojson.features[0];
+        let properties = feature.properties.as_ref().unwrap();
+        assert_eq!(properties["id"], 0);
+        assert_eq!(properties["name"], "Bank, City");
+        assert_eq!(properties["pop"], 2.0);
+        assert_eq!(
+            feature.geometry.as_ref().unwrap().value,
+            geojson::Value::Point(vec![-0.1, 51.5])
+        );
+    }
+}
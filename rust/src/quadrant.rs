@@ -0,0 +1,552 @@
+use crate::{rgb_buffer, Chart, Format, PopulationPoint, Station};
+use geo::{Geometry, GeometryCollection, LineString};
+use plotters::chart::SeriesAnno;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use plotters::style::full_palette::{GREY_300, ORANGE};
+use std::fmt;
+use std::fs;
+use std::ops::Range;
+use std::str::FromStr;
+
+/// The quadrant of the scatterplot of population against the stations near
+/// a point, split at the thresholds of both
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quadrant {
+    /// Normal population but lots of stations
+    Red,
+    /// High population but few stations
+    Orange,
+    /// High population and lots of stations
+    Blue,
+    /// Low population and few stations
+    Green,
+}
+
+impl FromStr for Quadrant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "red" => Ok(Self::Red),
+            "orange" => Ok(Self::Orange),
+            "blue" => Ok(Self::Blue),
+            "green" => Ok(Self::Green),
+            _ => Err(format!(
+                "unknown quadrant `{}`, expected one of: \
+                 red, orange, blue, green",
+                s
+            )),
+        }
+    }
+}
+
+impl Quadrant {
+    pub const ALL: [Quadrant; 4] =
+        [Self::Red, Self::Orange, Self::Blue, Self::Green];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Red => "red",
+            Self::Orange => "orange",
+            Self::Blue => "blue",
+            Self::Green => "green",
+        }
+    }
+
+    pub fn color(self) -> RGBColor {
+        match self {
+            Self::Red => RED,
+            Self::Orange => ORANGE,
+            Self::Blue => BLUE,
+            Self::Green => GREEN,
+        }
+    }
+}
+
+/// How the threshold of an axis is chosen from the values of the points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Split {
+    /// The percentile of the points, from 0 to 100, interpolated between
+    /// them
+    Percentile(f64),
+    /// The value below which that percentage of the population lives
+    WeightedPercentile(f64),
+    Mean,
+    Median,
+    /// The natural break into two classes (Jenks), which minimises the
+    /// squared deviations of the values from the means of their classes
+    Jenks,
+    /// This value
+    Value(f64),
+}
+
+impl FromStr for Split {
+    type Err = String;
+
+    /// percentile:P, weighted:P, mean, median, jenks or a number
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let percent = |p: &str| {
+            p.trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|p| (0.0..=100.0).contains(p))
+                .ok_or_else(|| format!("`{}` is not a percentage", p))
+        };
+        match s.split_once(':') {
+            Some(("percentile", p)) => Ok(Self::Percentile(percent(p)?)),
+            Some(("weighted", p)) => Ok(Self::WeightedPercentile(percent(p)?)),
+            None if s == "mean" => Ok(Self::Mean),
+            None if s == "median" => Ok(Self::Median),
+            None if s == "jenks" => Ok(Self::Jenks),
+            _ => match s.trim().parse::<f64>() {
+                Ok(value) if value.is_finite() => Ok(Self::Value(value)),
+                _ => Err(format!(
+                    "unknown split `{}`, expected one of: percentile:P, \
+                     weighted:P, mean, median, jenks or a number",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Percentile(p) => write!(f, "percentile:{}", p),
+            Self::WeightedPercentile(p) => write!(f, "weighted:{}", p),
+            Self::Mean => write!(f, "mean"),
+            Self::Median => write!(f, "median"),
+            Self::Jenks => write!(f, "jenks"),
+            Self::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Split {
+    /// The threshold of the values, where weights are the populations of
+    /// the points for the weighted percentile. NaN without any values
+    pub fn threshold(self, values: &[f64], weights: &[f64]) -> f64 {
+        if values.is_empty() {
+            return f64::NAN;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        match self {
+            Self::Percentile(p) => percentile_of_sorted(&sorted, p),
+            Self::WeightedPercentile(p) => {
+                weighted_percentile(values, weights, p)
+                    .unwrap_or_else(|| percentile_of_sorted(&sorted, p))
+            }
+            Self::Mean => values.iter().sum::<f64>() / values.len() as f64,
+            Self::Median => percentile_of_sorted(&sorted, 50.0),
+            Self::Jenks => jenks_break(&sorted),
+            Self::Value(value) => value,
+        }
+    }
+}
+
+/// Linearly interpolated between the closest ranks, like numpy
+fn percentile_of_sorted(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (lo, hi) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (rank - lo as f64)
+}
+
+/// The smallest value where the weights of the values up to it reach p
+/// percent of the total weight, or None if the weights add up to nothing
+fn weighted_percentile(values: &[f64], weights: &[f64], p: f64) -> Option<f64> {
+    let mut pairs: Vec<_> = values
+        .iter()
+        .copied()
+        .zip(weights.iter().copied())
+        .collect();
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total = pairs.iter().map(|(_, w)| w).sum::<f64>();
+    if total.partial_cmp(&0.0) != Some(std::cmp::Ordering::Greater) {
+        return None;
+    }
+    let target = p / 100.0 * total;
+    let mut cumulative = 0.0;
+    for (value, weight) in &pairs {
+        cumulative += weight;
+        if cumulative >= target {
+            return Some(*value);
+        }
+    }
+    pairs.last().map(|(value, _)| *value)
+}
+
+/// The largest value of the lower class of the best break into two classes
+fn jenks_break(sorted: &[f64]) -> f64 {
+    let n = sorted.len();
+    let (mut sums, mut squares) = (vec![0.0; n + 1], vec![0.0; n + 1]);
+    for (i, x) in sorted.iter().enumerate() {
+        sums[i + 1] = sums[i] + x;
+        squares[i + 1] = squares[i] + x * x;
+    }
+    // the squared deviations of the values from i to j from their mean
+    let deviations = |i: usize, j: usize| {
+        let (sum, k) = (sums[j] - sums[i], (j - i) as f64);
+        squares[j] - squares[i] - sum * sum / k
+    };
+    // the values on the threshold are below it, so equal values can't be
+    // split between the classes
+    (1..n)
+        .filter(|k| sorted[k - 1] < sorted[*k])
+        .min_by(|a, b| {
+            let cost = |k: usize| deviations(0, k) + deviations(k, n);
+            cost(*a).total_cmp(&cost(*b))
+        })
+        .map_or(sorted[n - 1], |k| sorted[k - 1])
+}
+
+/// How both axes of the quadrants are split
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Splits {
+    pub pop: Split,
+    pub n_stations: Split,
+}
+
+impl Default for Splits {
+    /// The third quartile of both
+    fn default() -> Self {
+        Self {
+            pop: Split::Percentile(75.0),
+            n_stations: Split::Percentile(75.0),
+        }
+    }
+}
+
+/// Where the quadrants are split
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Thresholds {
+    pub pop: f64,
+    /// The number of stations near a point, or the sum of their weights
+    pub n_stations: f64,
+    /// How they were chosen
+    pub splits: Splits,
+}
+
+impl Thresholds {
+    /// Split the population and the stations near every point, weighting
+    /// the points by their population for a weighted percentile
+    pub fn new(pops: &[f64], n_stations: &[f64], splits: Splits) -> Self {
+        Self {
+            pop: splits.pop.threshold(pops, pops),
+            n_stations: splits.n_stations.threshold(n_stations, pops),
+            splits,
+        }
+    }
+
+    /// The quadrant of a point, where the points on a threshold count as
+    /// below it
+    pub fn classify(&self, pop: f64, n_stations: f64) -> Quadrant {
+        match (pop > self.pop, n_stations > self.n_stations) {
+            (false, true) => Quadrant::Red,
+            (true, false) => Quadrant::Orange,
+            (true, true) => Quadrant::Blue,
+            (false, false) => Quadrant::Green,
+        }
+    }
+
+    /// The thresholds of the population and the stations, and how they
+    /// were chosen, for the legends
+    pub fn labels(&self) -> [String; 2] {
+        let label = |name: &str, threshold: f64, split: Split| match split {
+            Split::Value(_) => format!("{} > {}", name, threshold),
+            _ => format!("{} > {} ({})", name, threshold, split),
+        };
+        [
+            label("population", self.pop, self.splits.pop),
+            label("stations", self.n_stations, self.splits.n_stations),
+        ]
+    }
+}
+
+/// A map of every population point in the CRS of the city, colored by its
+/// quadrant over a grey background of all points
+pub struct QuadrantMap<'a> {
+    pub points: &'a [PopulationPoint],
+    /// The quadrant of every point, in the same order as the points
+    pub quadrants: &'a [Quadrant],
+    /// The city boundary to outline, in the CRS of the city
+    pub boundary: Option<&'a GeometryCollection>,
+    /// The stations to mark
+    pub stations: Option<&'a [Station]>,
+    /// The thresholds the quadrants were split at, to list in the legend
+    pub thresholds: Option<&'a Thresholds>,
+    /// Draw every quadrant in its own panel instead of all in one
+    pub facet: bool,
+    /// The width of the image in pixels. The height follows from the
+    /// extent of the points, so that a meter is as long in x as in y
+    pub width: u32,
+    /// The radius of every point in pixels, or 0 for a single pixel
+    pub point_size: u32,
+}
+
+/// The margin around every panel, and the height of its caption, in pixels
+const MARGIN: u32 = 10;
+const CAPTION: u32 = 40;
+
+/// The rings of every polygon in the geometry
+fn rings(geometry: &Geometry) -> Vec<&LineString> {
+    match geometry {
+        Geometry::Polygon(p) => {
+            std::iter::once(p.exterior()).chain(p.interiors()).collect()
+        }
+        Geometry::MultiPolygon(mp) => mp
+            .iter()
+            .flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors()))
+            .collect(),
+        Geometry::GeometryCollection(gc) => gc.iter().flat_map(rings).collect(),
+        _ => vec![],
+    }
+}
+
+impl QuadrantMap<'_> {
+    /// The x and y ranges of the points, and of the boundary if drawn
+    fn extent(&self) -> ((f64, f64), (f64, f64)) {
+        let boundary = self.boundary.into_iter().flat_map(|b| {
+            b.iter()
+                .flat_map(rings)
+                .flat_map(|r| r.coords().map(|c| (c.x, c.y)))
+        });
+        let xys = self.points.iter().map(|p| (p.x, p.y)).chain(boundary);
+        xys.fold(
+            (
+                (f64::INFINITY, f64::NEG_INFINITY),
+                (f64::INFINITY, f64::NEG_INFINITY),
+            ),
+            |((x0, x1), (y0, y1)), (x, y)| {
+                ((x0.min(x), x1.max(x)), (y0.min(y), y1.max(y)))
+            },
+        )
+    }
+
+    /// The x and y ranges of the map, padded around the extent
+    fn ranges(&self) -> (Range<f64>, Range<f64>) {
+        let ((x0, x1), (y0, y1)) = self.extent();
+        // a single point or a line of them still needs an area to draw in
+        let pad = (x1 - x0).max(y1 - y0).max(1.0) * 0.02;
+        (x0 - pad..x1 + pad, y0 - pad..y1 + pad)
+    }
+
+    /// The panels in columns and rows
+    fn grid(&self) -> (u32, u32) {
+        if self.facet {
+            (2, 2)
+        } else {
+            (1, 1)
+        }
+    }
+
+    /// The size of the image in pixels, from the width and the extent
+    pub fn size(&self) -> (u32, u32) {
+        let (x_range, y_range) = self.ranges();
+        let (columns, rows) = self.grid();
+        let plot_width = (self.width / columns).saturating_sub(2 * MARGIN);
+        let aspect =
+            (y_range.end - y_range.start) / (x_range.end - x_range.start);
+        let panel_height =
+            (plot_width as f64 * aspect).ceil() as u32 + 2 * MARGIN + CAPTION;
+        (self.width, panel_height * rows)
+    }
+
+    /// Draw the map into path: SVG, raw RGB pixels or a bitmap such as
+    /// PNG, by its extension
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let size = self.size();
+        match Format::of(path) {
+            Format::Svg => {
+                self.draw(&SVGBackend::new(path, size).into_drawing_area())
+            }
+            Format::Bitmap => {
+                self.draw(&BitMapBackend::new(path, size).into_drawing_area())
+            }
+            Format::Rgb => fs::write(path, self.render_rgb()?)
+                .map_err(|e| format!("cannot write {}: {}", path, e).into()),
+        }
+    }
+
+    /// Draw the map into a buffer of RGB pixels, row by row, of self.size()
+    pub fn render_rgb(&self) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let size = self.size();
+        let mut buffer = rgb_buffer(size);
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, size)
+                .into_drawing_area();
+            self.draw(&root)?;
+        }
+        Ok(buffer)
+    }
+
+    /// Draw the map onto any backend, which should be self.size()
+    pub fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        if self.points.is_empty() {
+            return Err("no population points to draw".into());
+        }
+        let (x_range, y_range) = self.ranges();
+        let (columns, rows) = self.grid();
+        root.fill(&WHITE)?;
+
+        let panels: Vec<(_, &[Quadrant])> = if self.facet {
+            let areas = root.split_evenly((rows as usize, columns as usize));
+            areas.into_iter().zip(Quadrant::ALL.chunks(1)).collect()
+        } else {
+            vec![(root.clone(), &Quadrant::ALL[..])]
+        };
+
+        for (area, shown) in panels {
+            let n = self.quadrants.iter().filter(|q| shown.contains(q)).count();
+            let title = if self.facet {
+                format!("{} ({} points)", shown[0].name(), n)
+            } else {
+                format!("{} points", n)
+            };
+            let mut chart = ChartBuilder::on(&area)
+                .caption(title, ("sans-serif", 24_i32))
+                .margin(MARGIN)
+                .build_cartesian_2d(x_range.clone(), y_range.clone())?;
+
+            // points in the other quadrants of a facet are grey
+            let grey = self
+                .points
+                .iter()
+                .zip(self.quadrants)
+                .filter(|(_, q)| !self.facet || !shown.contains(q))
+                .map(|(p, _)| (p.x, p.y));
+            self.draw_points(&mut chart, grey, GREY_300)?;
+
+            for &quadrant in shown {
+                let points = self
+                    .points
+                    .iter()
+                    .zip(self.quadrants)
+                    .filter(|(_, q)| **q == quadrant)
+                    .map(|(p, _)| (p.x, p.y));
+                self.draw_points(&mut chart, points, quadrant.color())?
+                    .label(quadrant.name())
+                    .legend(move |(x, y)| {
+                        Circle::new((x, y), 4_i32, quadrant.color().filled())
+                    });
+            }
+
+            if let Some(boundary) = self.boundary {
+                chart.draw_series(boundary.iter().flat_map(rings).map(
+                    |r| {
+                        PathElement::new(
+                            r.coords().map(|c| (c.x, c.y)).collect::<Vec<_>>(),
+                            BLACK.stroke_width(1),
+                        )
+                    },
+                ))?;
+            }
+
+            if let Some(stations) = self.stations {
+                chart.draw_series(stations.iter().map(|s| {
+                    Circle::new((s.x, s.y), 3_i32, BLACK.stroke_width(1))
+                }))?;
+            }
+
+            if let Some(thresholds) = self.thresholds {
+                for label in thresholds.labels() {
+                    chart
+                        .draw_series(std::iter::empty::<Pixel<(f64, f64)>>())?
+                        .label(label)
+                        .legend(|(x, y)| EmptyElement::at((x, y)));
+                }
+            }
+
+            // a facet only has its own quadrant to list, besides the
+            // thresholds
+            if !self.facet || self.thresholds.is_some() {
+                chart
+                    .configure_series_labels()
+                    .position(SeriesLabelPosition::UpperRight)
+                    .background_style(WHITE.mix(0.8))
+                    .border_style(BLACK)
+                    .label_font(("sans-serif", 20_i32))
+                    .draw()?;
+            }
+        }
+
+        root.present()?;
+        Ok(())
+    }
+
+    fn draw_points<'c, 'a, DB: DrawingBackend>(
+        &self,
+        chart: &'c mut Chart<'a, DB>,
+        xys: impl Iterator<Item = (f64, f64)>,
+        color: RGBColor,
+    ) -> Result<&'c mut SeriesAnno<'a, DB>, Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static,
+    {
+        let anno = if self.point_size == 0 {
+            chart.draw_series(xys.map(|xy| Pixel::new(xy, color)))?
+        } else {
+            let size = self.point_size as i32;
+            chart.draw_series(
+                xys.map(|xy| Circle::new(xy, size, color.filled())),
+            )?
+        };
+        Ok(anno)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let pops = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let n_stations = [0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0, 9.0];
+        let t = Thresholds::new(&pops, &n_stations, Splits::default());
+        assert_eq!(t.classify(t.pop, t.n_stations), Quadrant::Green);
+        assert_eq!(t.classify(t.pop, t.n_stations + 1.0), Quadrant::Red);
+        assert_eq!(t.classify(t.pop + 1.0, t.n_stations), Quadrant::Orange);
+        assert_eq!(t.classify(t.pop + 1.0, 100.0), Quadrant::Blue);
+        assert_eq!("orange".parse(), Ok(Quadrant::Orange));
+        assert!("purple".parse::<Quadrant>().is_err());
+    }
+
+    #[test]
+    fn test_splits() {
+        let pops = [1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0];
+        let threshold = |split: &str| {
+            split.parse::<Split>().unwrap().threshold(&pops, &pops)
+        };
+        assert_eq!(threshold("percentile:75"), 6.25);
+        assert_eq!(threshold("median"), 4.5);
+        assert_eq!(threshold("mean"), 4.5);
+        // half of the 36 people live at points of up to 6
+        assert_eq!(threshold("weighted:50"), 6.0);
+        assert_eq!(threshold("12.5"), 12.5);
+        assert!("percentile:101".parse::<Split>().is_err());
+        assert!("weighted".parse::<Split>().is_err());
+        assert!("mode".parse::<Split>().is_err());
+
+        let n_stations = [0.0, 0.0, 0.0, 1.0, 1.0, 2.0, 3.0, 9.0];
+        assert_eq!(Split::Jenks.threshold(&n_stations, &pops), 3.0);
+        assert_eq!(Split::Jenks.threshold(&[0.0, 0.0, 5.0, 6.0], &pops), 0.0);
+        assert_eq!(Split::Jenks.threshold(&[2.0, 2.0], &pops), 2.0);
+
+        let splits = Splits {
+            pop: Split::Median,
+            n_stations: Split::Value(1.0),
+        };
+        let t = Thresholds::new(&pops, &n_stations, splits);
+        assert_eq!(t.labels()[0], "population > 4.5 (median)");
+        assert_eq!(t.labels()[1], "stations > 1");
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{column_index, parse_field, CsvStream};
+use csv::StringRecord;
+use geo::transform::Proj;
+use geo::{Coord, GeometryCollection, MapCoords};
+use std::cell::RefCell;
+
+/// The CRS of every lat/lon file
+pub const WGS84: &str = "EPSG:4326";
+
+thread_local! {
+    // Proj isn't Send, so every rayon thread creates its own
+    static PROJ: RefCell<Option<(String, Proj)>> = const { RefCell::new(None) };
+}
+
+/// Transform a lon/lat pair from WGS84 into the target CRS
+pub fn to_crs(lon: f64, lat: f64, crs: &str) -> Result<(f64, f64), String> {
+    PROJ.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        if !matches!(&*cell, Some((target, _)) if target == crs) {
+            let proj = Proj::new_known_crs(WGS84, crs, None)
+                .map_err(|e| format!("cannot transform to {}: {}", crs, e))?;
+            *cell = Some((crs.to_string(), proj));
+        }
+        let (_, proj) = cell.as_ref().unwrap();
+        proj.convert((lon, lat)).map_err(|e| e.to_string())
+    })
+}
+
+/// Transform every coordinate of the geometries from WGS84 into the target
+/// CRS, such as the city boundaries to draw over the points
+pub fn reproject_geometries(
+    geometries: &GeometryCollection,
+    crs: &str,
+) -> Result<GeometryCollection, String> {
+    geometries.try_map_coords(|c| {
+        let (x, y) = to_crs(c.x, c.y, crs)?;
+        Ok(Coord { x, y })
+    })
+}
+
+/// Stream a csv with lon/lat columns into a csv with the same columns plus
+/// x and y in the target CRS. If the input already has x and y columns,
+/// they are overwritten. Only a chunk of rows is in memory at any time,
+/// so it works on files larger than RAM.
+/// Returns the number of rows written
+pub fn reproject_csv(
+    input: &str,
+    output: &str,
+    lon_col: &str,
+    lat_col: &str,
+    crs: &str,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut stream = CsvStream::open(input)?;
+    let mut headers = stream.headers()?;
+    let lon = column_index(&headers, lon_col)?;
+    let lat = column_index(&headers, lat_col)?;
+
+    let mut xy_idx = |name: &str| {
+        column_index(&headers, name).unwrap_or_else(|_| {
+            headers.push_field(name);
+            headers.len() - 1
+        })
+    };
+    let (x, y) = (xy_idx("x"), xy_idx("y"));
+
+    let stats = stream.par_filter_map(output, &headers, |record| {
+        let lon_lat = [(lon, lon_col), (lat, lat_col)];
+        transform_record(record, lon_lat, (x, y), crs).map(Some)
+    })?;
+    Ok(stats.written)
+}
+
+fn transform_record(
+    record: StringRecord,
+    [(lon, lon_col), (lat, lat_col)]: [(usize, &str); 2],
+    (x_idx, y_idx): (usize, usize),
+    crs: &str,
+) -> Result<StringRecord, String> {
+    let lon = parse_field(&record, lon, lon_col)?;
+    let lat = parse_field(&record, lat, lat_col)?;
+    let (x, y) = to_crs(lon, lat, crs)?;
+    let (x, y) = (x.to_string(), y.to_string());
+
+    let mut out = StringRecord::new();
+    for (i, field) in record.iter().enumerate() {
+        match i {
+            i if i == x_idx => out.push_field(&x),
+            i if i == y_idx => out.push_field(&y),
+            _ => out.push_field(field),
+        }
+    }
+    // either column can be new, and x comes before y if both are
+    for (idx, value) in [(x_idx, &x), (y_idx, &y)] {
+        if idx >= record.len() {
+            out.push_field(value);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn record(line: &str) -> StringRecord {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(line.as_bytes());
+        reader.records().next().unwrap().unwrap()
+    }
+
+    const LON_LAT_21: [(usize, &str); 2] = [(2, "lon"), (1, "lat")];
+
+    fn xy(record: &StringRecord, x: usize, y: usize) -> (f64, f64) {
+        (record[x].parse().unwrap(), record[y].parse().unwrap())
+    }
+
+    #[test]
+    fn test_append_xy() {
+        let r = record("Bank,51.4475,-0.1270");
+        let out = transform_record(r, LON_LAT_21, (3, 4), "EPSG:3857").unwrap();
+        assert_eq!(&out[0], "Bank");
+        let (x, y) = xy(&out, 3, 4);
+        assert!((x - -14137.575).abs() < 0.01);
+        assert!((y - 6700836.315).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_overwrite_xy() {
+        let r = record("1,2,-0.1270,51.4475,3");
+        let lon_lat = [(2, "lon"), (3, "lat")];
+        let out = transform_record(r, lon_lat, (0, 1), "EPSG:3857").unwrap();
+        assert_eq!(out.len(), 5);
+        assert_eq!(&out[4], "3");
+        let (x, _) = xy(&out, 0, 1);
+        assert!((x - -14137.575).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_append_one_of_xy() {
+        // y is already there, so only x is appended
+        let r = record("Bank,51.4475,-0.1270,0");
+        let out = transform_record(r, LON_LAT_21, (4, 3), "EPSG:3857").unwrap();
+        assert_eq!(out.len(), 5);
+        let (x, y) = xy(&out, 4, 3);
+        assert!((x - -14137.575).abs() < 0.01);
+        assert!((y - 6700836.315).abs() < 0.01);
+
+        // x is already there, so only y is appended
+        let r = record("Bank,51.4475,-0.1270,0");
+        let out = transform_record(r, LON_LAT_21, (3, 4), "EPSG:3857").unwrap();
+        assert_eq!(out.len(), 5);
+        let (x, _) = xy(&out, 3, 4);
+        assert!((x - -14137.575).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_column_in_error() {
+        let r = record("Bank,north,-0.1270");
+        let lon_lat = [(2, "longitude"), (1, "latitude")];
+        let e = transform_record(r, lon_lat, (3, 4), "EPSG:3857").unwrap_err();
+        assert!(e.contains("latitude"), "{}", e);
+    }
+}
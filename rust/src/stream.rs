@@ -0,0 +1,135 @@
+use csv::StringRecord;
+use rayon::prelude::*;
+use std::fs::File;
+
+/// How many rows are held in memory at once
+const CHUNK_SIZE: usize = 1 << 16;
+
+/// A csv that is read a chunk at a time, so that files larger than RAM can
+/// be processed
+pub struct CsvStream {
+    path: String,
+    reader: csv::Reader<File>,
+    /// The size of the file, for the progress
+    len: u64,
+}
+
+/// How many rows were read and written by a stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub read: usize,
+    pub written: usize,
+}
+
+impl CsvStream {
+    pub fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = File::open(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_string(),
+            reader: csv::Reader::from_reader(file),
+            len,
+        })
+    }
+
+    pub fn headers(&mut self) -> Result<StringRecord, csv::Error> {
+        self.reader.headers().cloned()
+    }
+
+    /// Run f on every row in parallel, and write the rows it returns to the
+    /// output in the same order as the input. Rows that f returns None for
+    /// are dropped. Prints the progress to stderr
+    pub fn par_filter_map<F>(
+        mut self,
+        output: &str,
+        headers: &StringRecord,
+        f: F,
+    ) -> Result<StreamStats, Box<dyn std::error::Error>>
+    where
+        F: Fn(StringRecord) -> Result<Option<StringRecord>, String> + Sync,
+    {
+        let mut writer = csv::Writer::from_path(output)
+            .map_err(|e| format!("cannot write {}: {}", output, e))?;
+        writer.write_record(headers)?;
+
+        let mut stats = StreamStats {
+            read: 0,
+            written: 0,
+        };
+        let mut records = self.reader.records();
+        loop {
+            let chunk = records
+                .by_ref()
+                .take(CHUNK_SIZE)
+                .collect::<Result<Vec<_>, _>>()?;
+            if chunk.is_empty() {
+                break;
+            }
+            stats.read += chunk.len();
+
+            // collecting an indexed parallel iterator keeps the order
+            let result = chunk
+                .into_par_iter()
+                .map(&f)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| format!("{}: {}", self.path, e))?;
+
+            for record in result.iter().flatten() {
+                writer.write_record(record)?;
+                stats.written += 1;
+            }
+
+            let pos = records.reader().position().byte();
+            eprint!(
+                "\r{}: {:.1}%, {} rows read, {} written",
+                self.path,
+                pos as f64 / self.len.max(1) as f64 * 100.0,
+                stats.read,
+                stats.written
+            );
+        }
+        eprintln!();
+
+        writer.flush()?;
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_order_across_chunks() {
+        let dir = std::env::temp_dir().join("atupp_stream_test");
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("in.csv");
+        let output = dir.join("out.csv");
+
+        let n = CHUNK_SIZE * 2 + 10;
+        let rows: String = (0..n).map(|i| format!("{}\n", i)).collect();
+        fs::write(&input, "i\n".to_string() + &rows).unwrap();
+
+        let mut stream = CsvStream::open(input.to_str().unwrap()).unwrap();
+        let headers = stream.headers().unwrap();
+        let stats = stream
+            .par_filter_map(output.to_str().unwrap(), &headers, |r| {
+                let i: usize = r[0].parse().unwrap();
+                Ok(i.is_multiple_of(3).then_some(r))
+            })
+            .unwrap();
+        assert_eq!(stats.read, n);
+        assert_eq!(stats.written, n.div_ceil(3));
+
+        let written: Vec<usize> = fs::read_to_string(&output)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|l| l.parse().unwrap())
+            .collect();
+        let expected: Vec<_> = (0..n).filter(|i| i.is_multiple_of(3)).collect();
+        assert_eq!(written, expected);
+    }
+}
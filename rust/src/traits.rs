@@ -1,31 +1,46 @@
-use rstar::RTree;
+use crate::{rgb_buffer, Format, PopulationPoint, StationPoint};
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use rstar::{RTree, RTreeObject};
+use std::fs;
 
 /// Describes a visualization that searches the R* tree and save the result as csv
 /// The result can be used to plot with python
-/// The generic type T is anything the search function returns
-pub trait Search<T> {
+/// The generic type T is anything the search function returns, and S is
+/// what the tree stores for every station, which is the library's
+/// StationPoint unless the visualization brings its own station type
+pub trait Search<T, S: RTreeObject = StationPoint> {
     /// Search the tree and output it to a file (actually stdout)
     /// The python script can read the result and plot it
-    fn search_to_file(&self, tree: &RTree<(f64, f64)>, pp_lines: &[&str]);
+    fn search_to_file(
+        &self,
+        tree: &RTree<S>,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>>;
 
     /// The function that searches the R* tree.
     /// The stations are stored in the tree. For every population point
-    /// in pp_lines, the function searches for the nearest neighbours within
-    /// max_distance. It returns anything the visualization needs, such as...
+    /// in points, the function searches for the nearest neighbours within
+    /// max_distance. It returns anything the subcommand of atupp needs, such
+    /// as...
     ///
-    /// cumulative_props.rs:
-    /// - the population of the point if a station within max_distance is found
+    /// props:
+    /// - the population within max_distance of a station
     ///
-    /// stations_within_pp.rs:
-    /// - the number of stations within max_distance of any population point
+    /// box:
+    /// - the number of stations within max_distance of every population point
     ///
-    /// quadrants.rs:
+    /// quadrants:
     /// - the population of every point and the number of stations within
     ///   max_distance of it
+    ///
+    /// nearest, catchments, fca, lisa...:
+    /// - the nearest station, the catchment or the accessibility score of
+    ///   every point
     fn search(
         &self,
-        tree: &RTree<(f64, f64)>,
-        pp_lines: &[&str],
+        tree: &RTree<S>,
+        points: &[PopulationPoint],
         max_distance: f64,
     ) -> T;
 }
@@ -34,12 +49,71 @@ pub trait Search<T> {
 /// It requires the visualization to implement Search, as it relies on the search
 /// function. The result of the search function can be anything (U), as long
 /// as it can be transformed into T
-pub trait Plot<T, U>: Search<U> {
+pub trait Plot<T, U, S: RTreeObject = StationPoint>:
+    Search<U, S> + Draw<T>
+{
     /// Search the tree and immediately plot the results with rust.
     /// Use when python cannot handle the amount of data
-    fn search_to_plot(&self, tree: &RTree<(f64, f64)>, pp_lines: &[&str]);
-
-    /// The function that does the plotting
-    fn plot(&self, data: T) -> Result<(), Box<dyn std::error::Error>>;
+    fn search_to_plot(
+        &self,
+        tree: &RTree<S>,
+        points: &[PopulationPoint],
+    ) -> Result<(), Box<dyn std::error::Error>>;
 }
 
+/// Draws a chart of T onto any backend, and saves it in the format of its
+/// output file. Plot draws what it searched with it, but a chart can also be
+/// drawn from data that was searched elsewhere, such as for every city
+pub trait Draw<T> {
+    /// Where to write the plot. Its extension picks the format, see Format
+    fn out_file(&self) -> &str;
+
+    /// The size of the plot in pixels
+    fn size(&self) -> (u32, u32);
+
+    /// The function that does the plotting, onto any backend
+    fn draw<DB: DrawingBackend>(
+        &self,
+        root: &DrawingArea<DB, Shift>,
+        data: T,
+    ) -> Result<(), Box<dyn std::error::Error>>
+    where
+        DB::ErrorType: 'static;
+
+    /// Plot into out_file: SVG, raw RGB pixels or a bitmap such as PNG,
+    /// by its extension
+    fn plot(&self, data: T) -> Result<(), Box<dyn std::error::Error>> {
+        let (path, size) = (self.out_file(), self.size());
+        match Format::of(path) {
+            Format::Svg => {
+                let root = SVGBackend::new(path, size).into_drawing_area();
+                self.draw(&root, data)
+            }
+            Format::Bitmap => {
+                let root = BitMapBackend::new(path, size).into_drawing_area();
+                self.draw(&root, data)
+            }
+            Format::Rgb => {
+                let buffer = self.render_rgb(data)?;
+                fs::write(path, buffer)
+                    .map_err(|e| format!("cannot write {}: {}", path, e).into())
+            }
+        }
+    }
+
+    /// Plot into a buffer of RGB pixels, row by row, to embed elsewhere
+    fn render_rgb(
+        &self,
+        data: T,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let size = self.size();
+        let mut buffer = rgb_buffer(size);
+        {
+            let root = BitMapBackend::with_buffer(&mut buffer, size)
+                .into_drawing_area();
+            self.draw(&root, data)?;
+            root.present()?;
+        }
+        Ok(buffer)
+    }
+}